@@ -11,6 +11,40 @@ export interface Config {
   ms_per_block: number | string | bigint;
 }
 
+export interface ContentLimits {
+  max_name_len: number;
+  max_greeting_len: number;
+}
+
+export interface RateLimits {
+  cooldown_ms: number | string | bigint;
+  max_calls_per_session: number;
+}
+
+export type HelloError =
+  | { EmptyInput: null }
+  | { TooLong: { max_len: number } }
+  | { ControlCharacter: null }
+  | { CooldownActive: { next_call_at: number | string | bigint } }
+  | { SessionQuotaExceeded: null }
+  | { Unauthorized: null };
+
+export interface StateEnvelope {
+  version: number;
+  payload: `0x${string}`;
+}
+
+export type MigrationError =
+  | { UnsupportedVersion: number }
+  | { MissingMigration: number }
+  | { InvalidPayload: number };
+
+export interface GreetingRecord {
+  author: ActorId;
+  message: string;
+  timestamp: number | string | bigint;
+}
+
 export interface IoHelloState {
   greeting: string;
   user_greetings: Array<[ActorId, string]>;
@@ -21,21 +55,63 @@ export interface SignatureData {
   key: ActorId;
   duration: number | string | bigint;
   allowed_actions: Array<ActionsForSession>;
+  spend_limits: Array<SpendLimit>;
+  max_calls: number | null;
+  scope: SessionScope;
+  allow_renewal: boolean;
+}
+
+export type ActionsForSession = 'SayHello' | 'SayPersonalHello' | 'SetGreeting' | 'GrantEditor' | 'RevokeEditor';
+
+export interface SpendLimit {
+  token: ActorId;
+  total: number | string | bigint;
+  per_call: number | string | bigint;
 }
 
-export type ActionsForSession = 'SayHello' | 'SayPersonalHello' | 'SetGreeting';
+export interface SessionScope {
+  allowed_recipients: Array<ActorId>;
+  allowed_tokens: Array<ActorId>;
+  max_value: number | string | bigint | null;
+  allowed_grantees: Array<ActorId>;
+}
 
 export interface SessionData {
   key: ActorId;
   expires: number | string | bigint;
   allowed_actions: Array<ActionsForSession>;
   expires_at_block: number;
+  allowances: Array<SpendAllowance>;
+  max_calls: number | null;
+  scope: SessionScope;
+  allow_renewal: boolean;
+  created_at: number | string | bigint;
 }
 
+export interface SpendAllowance {
+  token: ActorId;
+  remaining: number | string | bigint;
+  per_call: number | string | bigint;
+}
+
+export interface SessionActivity {
+  action: ActionsForSession;
+  block: number;
+  key: ActorId;
+}
+
+export type DeletionReason = 'Expired' | 'ByOwner' | 'ByProgram';
+
 export type Events =
-  | { Hello: null }
-  | { PersonalHello: { actor_id: ActorId; str: string } }
-  | { GreetingSet: string };
+  | { Hello: [ActorId, number | string | bigint] }
+  | { PersonalHello: [ActorId, string, number | string | bigint] }
+  | { GreetingSet: string }
+  | { EditorGranted: ActorId }
+  | { EditorRevoked: ActorId }
+  | { ContentLimitsSet: ContentLimits }
+  | { RateLimitsSet: RateLimits }
+  | { StateImported: number }
+  | { StateSealed: null };
 
 const types = {
   Config: {
@@ -43,6 +119,40 @@ const types = {
     minimum_session_duration_ms: 'u64',
     ms_per_block: 'u64',
   },
+  ContentLimits: {
+    max_name_len: 'u32',
+    max_greeting_len: 'u32',
+  },
+  RateLimits: {
+    cooldown_ms: 'u64',
+    max_calls_per_session: 'u32',
+  },
+  HelloError: {
+    _enum: {
+      EmptyInput: 'Null',
+      TooLong: { max_len: 'u32' },
+      ControlCharacter: 'Null',
+      CooldownActive: { next_call_at: 'u64' },
+      SessionQuotaExceeded: 'Null',
+      Unauthorized: 'Null',
+    },
+  },
+  StateEnvelope: {
+    version: 'u32',
+    payload: 'Vec<u8>',
+  },
+  MigrationError: {
+    _enum: {
+      UnsupportedVersion: 'u32',
+      MissingMigration: 'u32',
+      InvalidPayload: 'u32',
+    },
+  },
+  GreetingRecord: {
+    author: '[u8;32]',
+    message: 'String',
+    timestamp: 'u64',
+  },
   IoHelloState: {
     greeting: 'String',
     user_greetings: 'Vec<([u8;32], String)>',
@@ -52,25 +162,60 @@ const types = {
     key: '[u8;32]',
     duration: 'u64',
     allowed_actions: 'Vec<ActionsForSession>',
+    spend_limits: 'Vec<SpendLimit>',
+    max_calls: 'Option<u32>',
+    scope: 'SessionScope',
+    allow_renewal: 'bool',
   },
   ActionsForSession: {
-    _enum: ['SayHello', 'SayPersonalHello', 'SetGreeting'],
+    _enum: ['SayHello', 'SayPersonalHello', 'SetGreeting', 'GrantEditor', 'RevokeEditor'],
+  },
+  SpendLimit: {
+    token: '[u8;32]',
+    total: 'u128',
+    per_call: 'u128',
+  },
+  SessionScope: {
+    allowed_recipients: 'Vec<[u8;32]>',
+    allowed_tokens: 'Vec<[u8;32]>',
+    max_value: 'Option<u128>',
+    allowed_grantees: 'Vec<[u8;32]>',
   },
   SessionData: {
     key: '[u8;32]',
     expires: 'u64',
     allowed_actions: 'Vec<ActionsForSession>',
     expires_at_block: 'u32',
+    allowances: 'Vec<SpendAllowance>',
+    max_calls: 'Option<u32>',
+    scope: 'SessionScope',
+    allow_renewal: 'bool',
+    created_at: 'u64',
   },
-  PersonalHello: {
-    actor_id: '[u8;32]',
-    str: 'String',
+  SpendAllowance: {
+    token: '[u8;32]',
+    remaining: 'u128',
+    per_call: 'u128',
+  },
+  SessionActivity: {
+    action: 'ActionsForSession',
+    block: 'u32',
+    key: '[u8;32]',
+  },
+  DeletionReason: {
+    _enum: ['Expired', 'ByOwner', 'ByProgram'],
   },
   Events: {
     _enum: {
-      Hello: 'Null',
-      PersonalHello: 'PersonalHello',
+      Hello: '([u8;32], u64)',
+      PersonalHello: '([u8;32], String, u64)',
       GreetingSet: 'String',
+      EditorGranted: '[u8;32]',
+      EditorRevoked: '[u8;32]',
+      ContentLimitsSet: 'ContentLimits',
+      RateLimitsSet: 'RateLimits',
+      StateImported: 'u32',
+      StateSealed: 'Null',
     },
   },
 };
@@ -97,13 +242,13 @@ export class Program {
     return this._programId;
   }
 
-  newCtorFromCode(code: Uint8Array | Buffer, config: Config): TransactionBuilder<null> {
+  newCtorFromCode(code: Uint8Array | Buffer, config: Config, limits: ContentLimits | null): TransactionBuilder<null> {
     const builder = new TransactionBuilder<null>(
       this.api,
       this.registry,
       'upload_program',
-      ['New', config],
-      '(String, Config)',
+      ['New', config, limits],
+      '(String, Config, Option<ContentLimits>)',
       'String',
       code,
     );
@@ -111,13 +256,13 @@ export class Program {
     return builder;
   }
 
-  newCtorFromCodeId(codeId: `0x${string}`, config: Config): TransactionBuilder<null> {
+  newCtorFromCodeId(codeId: `0x${string}`, config: Config, limits: ContentLimits | null): TransactionBuilder<null> {
     const builder = new TransactionBuilder<null>(
       this.api,
       this.registry,
       'create_program',
-      ['New', config],
-      '(String, Config)',
+      ['New', config, limits],
+      '(String, Config, Option<ContentLimits>)',
       'String',
       codeId,
     );
@@ -129,45 +274,153 @@ export class Program {
 export class Service {
   constructor(private _program: Program) {}
 
-  public helloWorld(session_for_account: ActorId | null): TransactionBuilder<Events> {
+  public grantEditor(to: ActorId, session_for_account: ActorId | null): TransactionBuilder<Events> {
     if (!this._program.programId) throw new Error('Program ID is not set');
     return new TransactionBuilder<Events>(
+      this._program.api,
+      this._program.registry,
+      'send_message',
+      ['Service', 'GrantEditor', to, session_for_account],
+      '(String, String, [u8;32], Option<[u8;32]>)',
+      'Events',
+      this._program.programId,
+    );
+  }
+
+  public helloWorld(session_for_account: ActorId | null): TransactionBuilder<{ ok: Events } | { err: HelloError }> {
+    if (!this._program.programId) throw new Error('Program ID is not set');
+    return new TransactionBuilder<{ ok: Events } | { err: HelloError }>(
       this._program.api,
       this._program.registry,
       'send_message',
       ['Service', 'HelloWorld', session_for_account],
       '(String, String, Option<[u8;32]>)',
-      'Events',
+      'Result<Events, HelloError>',
       this._program.programId,
     );
   }
 
-  public personalHello(name: string, session_for_account: ActorId | null): TransactionBuilder<Events> {
+  public importState(chunk: StateEnvelope): TransactionBuilder<{ ok: Events } | { err: MigrationError }> {
     if (!this._program.programId) throw new Error('Program ID is not set');
-    return new TransactionBuilder<Events>(
+    return new TransactionBuilder<{ ok: Events } | { err: MigrationError }>(
+      this._program.api,
+      this._program.registry,
+      'send_message',
+      ['Service', 'ImportState', chunk],
+      '(String, String, StateEnvelope)',
+      'Result<Events, MigrationError>',
+      this._program.programId,
+    );
+  }
+
+  public personalHello(name: string, session_for_account: ActorId | null): TransactionBuilder<{ ok: Events } | { err: HelloError }> {
+    if (!this._program.programId) throw new Error('Program ID is not set');
+    return new TransactionBuilder<{ ok: Events } | { err: HelloError }>(
       this._program.api,
       this._program.registry,
       'send_message',
       ['Service', 'PersonalHello', name, session_for_account],
       '(String, String, String, Option<[u8;32]>)',
+      'Result<Events, HelloError>',
+      this._program.programId,
+    );
+  }
+
+  public revokeEditor(from: ActorId, session_for_account: ActorId | null): TransactionBuilder<Events> {
+    if (!this._program.programId) throw new Error('Program ID is not set');
+    return new TransactionBuilder<Events>(
+      this._program.api,
+      this._program.registry,
+      'send_message',
+      ['Service', 'RevokeEditor', from, session_for_account],
+      '(String, String, [u8;32], Option<[u8;32]>)',
       'Events',
       this._program.programId,
     );
   }
 
-  public setGreeting(new_greeting: string, session_for_account: ActorId | null): TransactionBuilder<Events> {
+  public sealState(): TransactionBuilder<Events> {
     if (!this._program.programId) throw new Error('Program ID is not set');
     return new TransactionBuilder<Events>(
+      this._program.api,
+      this._program.registry,
+      'send_message',
+      ['Service', 'SealState'],
+      '(String, String)',
+      'Events',
+      this._program.programId,
+    );
+  }
+
+  public setContentLimits(limits: ContentLimits): TransactionBuilder<Events> {
+    if (!this._program.programId) throw new Error('Program ID is not set');
+    return new TransactionBuilder<Events>(
+      this._program.api,
+      this._program.registry,
+      'send_message',
+      ['Service', 'SetContentLimits', limits],
+      '(String, String, ContentLimits)',
+      'Events',
+      this._program.programId,
+    );
+  }
+
+  public setGreeting(new_greeting: string, session_for_account: ActorId | null): TransactionBuilder<{ ok: Events } | { err: HelloError }> {
+    if (!this._program.programId) throw new Error('Program ID is not set');
+    return new TransactionBuilder<{ ok: Events } | { err: HelloError }>(
       this._program.api,
       this._program.registry,
       'send_message',
       ['Service', 'SetGreeting', new_greeting, session_for_account],
       '(String, String, String, Option<[u8;32]>)',
+      'Result<Events, HelloError>',
+      this._program.programId,
+    );
+  }
+
+  public setRateLimits(rate_limits: RateLimits): TransactionBuilder<Events> {
+    if (!this._program.programId) throw new Error('Program ID is not set');
+    return new TransactionBuilder<Events>(
+      this._program.api,
+      this._program.registry,
+      'send_message',
+      ['Service', 'SetRateLimits', rate_limits],
+      '(String, String, RateLimits)',
       'Events',
       this._program.programId,
     );
   }
 
+  public async exportState(offset: number, limit: number, originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<StateEnvelope> {
+    const payload = this._program.registry.createType('(String, String, u32, u32)', ['Service', 'ExportState', offset, limit]).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, StateEnvelope)', reply.payload);
+    return result[2].toJSON() as StateEnvelope;
+  }
+
+  public async queryContentLimits(originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<ContentLimits> {
+    const payload = this._program.registry.createType('(String, String)', ['Service', 'QueryContentLimits']).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, ContentLimits)', reply.payload);
+    return result[2].toJSON() as ContentLimits;
+  }
+
   public async queryCounter(originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<bigint> {
     const payload = this._program.registry.createType('(String, String)', ['Service', 'QueryCounter']).toHex();
     const reply = await this._program.api.message.calculateReply({
@@ -183,6 +436,21 @@ export class Service {
     return result[2].toBigInt();
   }
 
+  public async queryEditors(originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<Array<ActorId>> {
+    const payload = this._program.registry.createType('(String, String)', ['Service', 'QueryEditors']).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, Vec<[u8;32]>)', reply.payload);
+    return result[2].toJSON() as Array<ActorId>;
+  }
+
   public async queryGreeting(originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<string> {
     const payload = this._program.registry.createType('(String, String)', ['Service', 'QueryGreeting']).toHex();
     const reply = await this._program.api.message.calculateReply({
@@ -198,6 +466,66 @@ export class Service {
     return result[2].toString();
   }
 
+  public async queryGreetingHistory(offset: number, limit: number, originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<Array<GreetingRecord>> {
+    const payload = this._program.registry.createType('(String, String, u32, u32)', ['Service', 'QueryGreetingHistory', offset, limit]).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, Vec<GreetingRecord>)', reply.payload);
+    return result[2].toJSON() as Array<GreetingRecord>;
+  }
+
+  public async queryNextCallAt(user: ActorId, originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<bigint> {
+    const payload = this._program.registry.createType('(String, String, [u8;32])', ['Service', 'QueryNextCallAt', user]).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, u64)', reply.payload);
+    return result[2].toBigInt();
+  }
+
+  public async queryOwner(originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<ActorId> {
+    const payload = this._program.registry.createType('(String, String)', ['Service', 'QueryOwner']).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, [u8;32])', reply.payload);
+    return result[2].toJSON() as ActorId;
+  }
+
+  public async queryRateLimits(originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<RateLimits> {
+    const payload = this._program.registry.createType('(String, String)', ['Service', 'QueryRateLimits']).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, RateLimits)', reply.payload);
+    return result[2].toJSON() as RateLimits;
+  }
+
   public async queryState(originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<IoHelloState> {
     const payload = this._program.registry.createType('(String, String)', ['Service', 'QueryState']).toHex();
     const reply = await this._program.api.message.calculateReply({
@@ -213,6 +541,51 @@ export class Service {
     return result[2].toJSON() as IoHelloState;
   }
 
+  public async queryStateVersion(originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<number> {
+    const payload = this._program.registry.createType('(String, String)', ['Service', 'QueryStateVersion']).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, u32)', reply.payload);
+    return result[2].toNumber();
+  }
+
+  public async queryTopGreeters(limit: number, originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<Array<[ActorId, number | string | bigint]>> {
+    const payload = this._program.registry.createType('(String, String, u32)', ['Service', 'QueryTopGreeters', limit]).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, Vec<([u8;32], u64)>)', reply.payload);
+    return result[2].toJSON() as Array<[ActorId, number | string | bigint]>;
+  }
+
+  public async queryUserCounter(user: ActorId, originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<bigint> {
+    const payload = this._program.registry.createType('(String, String, [u8;32])', ['Service', 'QueryUserCounter', user]).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, u64)', reply.payload);
+    return result[2].toBigInt();
+  }
+
   public async queryUserGreeting(user: ActorId, originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<string | null> {
     const payload = this._program.registry.createType('(String, String, [u8;32])', ['Service', 'QueryUserGreeting', user]).toHex();
     const reply = await this._program.api.message.calculateReply({
@@ -228,27 +601,43 @@ export class Service {
     return result[2].toJSON() as string | null;
   }
 
-  public subscribeToHelloEvent(callback: (data: null) => void | Promise<void>): Promise<() => void> {
+  public async queryUserHistory(user: ActorId, offset: number, limit: number, originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<Array<GreetingRecord>> {
+    const payload = this._program.registry.createType('(String, String, [u8;32], u32, u32)', ['Service', 'QueryUserHistory', user, offset, limit]).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, Vec<GreetingRecord>)', reply.payload);
+    return result[2].toJSON() as Array<GreetingRecord>;
+  }
+
+  public subscribeToHelloEvent(callback: (data: [ActorId, number | string | bigint]) => void | Promise<void>): Promise<() => void> {
     return this._program.api.gearEvents.subscribeToGearEvent('UserMessageSent', ({ data: { message } }) => {
       if (!message.source.eq(this._program.programId) || !message.destination.eq(ZERO_ADDRESS)) return;
       const payload = message.payload.toHex();
       if (getServiceNamePrefix(payload) === 'Service' && getFnNamePrefix(payload) === 'Hello') {
-        void Promise.resolve(callback(null)).catch(console.error);
+        void Promise.resolve(
+          callback(
+            this._program.registry.createType('(String, String, ([u8;32], u64))', message.payload)[2].toJSON() as [ActorId, number | string | bigint]
+          )
+        ).catch(console.error);
       }
     });
   }
 
-  public subscribeToPersonalHelloEvent(callback: (data: { actor_id: ActorId; str: string }) => void | Promise<void>): Promise<() => void> {
+  public subscribeToPersonalHelloEvent(callback: (data: [ActorId, string, number | string | bigint]) => void | Promise<void>): Promise<() => void> {
     return this._program.api.gearEvents.subscribeToGearEvent('UserMessageSent', ({ data: { message } }) => {
       if (!message.source.eq(this._program.programId) || !message.destination.eq(ZERO_ADDRESS)) return;
       const payload = message.payload.toHex();
       if (getServiceNamePrefix(payload) === 'Service' && getFnNamePrefix(payload) === 'PersonalHello') {
         void Promise.resolve(
           callback(
-            this._program.registry.createType('(String, String, PersonalHello)', message.payload)[2].toJSON() as {
-              actor_id: ActorId;
-              str: string;
-            }
+            this._program.registry.createType('(String, String, ([u8;32], String, u64))', message.payload)[2].toJSON() as [ActorId, string, number | string | bigint]
           )
         ).catch(console.error);
       }
@@ -268,11 +657,104 @@ export class Service {
       }
     });
   }
+
+  public subscribeToEditorGrantedEvent(callback: (data: ActorId) => void | Promise<void>): Promise<() => void> {
+    return this._program.api.gearEvents.subscribeToGearEvent('UserMessageSent', ({ data: { message } }) => {
+      if (!message.source.eq(this._program.programId) || !message.destination.eq(ZERO_ADDRESS)) return;
+      const payload = message.payload.toHex();
+      if (getServiceNamePrefix(payload) === 'Service' && getFnNamePrefix(payload) === 'EditorGranted') {
+        void Promise.resolve(
+          callback(
+            this._program.registry.createType('(String, String, [u8;32])', message.payload)[2].toJSON() as ActorId
+          )
+        ).catch(console.error);
+      }
+    });
+  }
+
+  public subscribeToEditorRevokedEvent(callback: (data: ActorId) => void | Promise<void>): Promise<() => void> {
+    return this._program.api.gearEvents.subscribeToGearEvent('UserMessageSent', ({ data: { message } }) => {
+      if (!message.source.eq(this._program.programId) || !message.destination.eq(ZERO_ADDRESS)) return;
+      const payload = message.payload.toHex();
+      if (getServiceNamePrefix(payload) === 'Service' && getFnNamePrefix(payload) === 'EditorRevoked') {
+        void Promise.resolve(
+          callback(
+            this._program.registry.createType('(String, String, [u8;32])', message.payload)[2].toJSON() as ActorId
+          )
+        ).catch(console.error);
+      }
+    });
+  }
+
+  public subscribeToContentLimitsSetEvent(callback: (data: ContentLimits) => void | Promise<void>): Promise<() => void> {
+    return this._program.api.gearEvents.subscribeToGearEvent('UserMessageSent', ({ data: { message } }) => {
+      if (!message.source.eq(this._program.programId) || !message.destination.eq(ZERO_ADDRESS)) return;
+      const payload = message.payload.toHex();
+      if (getServiceNamePrefix(payload) === 'Service' && getFnNamePrefix(payload) === 'ContentLimitsSet') {
+        void Promise.resolve(
+          callback(
+            this._program.registry.createType('(String, String, ContentLimits)', message.payload)[2].toJSON() as ContentLimits
+          )
+        ).catch(console.error);
+      }
+    });
+  }
+
+  public subscribeToRateLimitsSetEvent(callback: (data: RateLimits) => void | Promise<void>): Promise<() => void> {
+    return this._program.api.gearEvents.subscribeToGearEvent('UserMessageSent', ({ data: { message } }) => {
+      if (!message.source.eq(this._program.programId) || !message.destination.eq(ZERO_ADDRESS)) return;
+      const payload = message.payload.toHex();
+      if (getServiceNamePrefix(payload) === 'Service' && getFnNamePrefix(payload) === 'RateLimitsSet') {
+        void Promise.resolve(
+          callback(
+            this._program.registry.createType('(String, String, RateLimits)', message.payload)[2].toJSON() as RateLimits
+          )
+        ).catch(console.error);
+      }
+    });
+  }
+
+  public subscribeToStateImportedEvent(callback: (data: number) => void | Promise<void>): Promise<() => void> {
+    return this._program.api.gearEvents.subscribeToGearEvent('UserMessageSent', ({ data: { message } }) => {
+      if (!message.source.eq(this._program.programId) || !message.destination.eq(ZERO_ADDRESS)) return;
+      const payload = message.payload.toHex();
+      if (getServiceNamePrefix(payload) === 'Service' && getFnNamePrefix(payload) === 'StateImported') {
+        void Promise.resolve(
+          callback(
+            this._program.registry.createType('(String, String, u32)', message.payload)[2].toNumber()
+          )
+        ).catch(console.error);
+      }
+    });
+  }
+
+  public subscribeToStateSealedEvent(callback: (data: null) => void | Promise<void>): Promise<() => void> {
+    return this._program.api.gearEvents.subscribeToGearEvent('UserMessageSent', ({ data: { message } }) => {
+      if (!message.source.eq(this._program.programId) || !message.destination.eq(ZERO_ADDRESS)) return;
+      const payload = message.payload.toHex();
+      if (getServiceNamePrefix(payload) === 'Service' && getFnNamePrefix(payload) === 'StateSealed') {
+        void Promise.resolve(callback(null)).catch(console.error);
+      }
+    });
+  }
 }
 
 export class Session {
   constructor(private _program: Program) {}
 
+  public cleanUpSessions(): TransactionBuilder<number> {
+    if (!this._program.programId) throw new Error('Program ID is not set');
+    return new TransactionBuilder<number>(
+      this._program.api,
+      this._program.registry,
+      'send_message',
+      ['Session', 'CleanUpSessions'],
+      '(String, String)',
+      'u32',
+      this._program.programId,
+    );
+  }
+
   public createSession(signature_data: SignatureData, signature: `0x${string}` | null): TransactionBuilder<null> {
     if (!this._program.programId) throw new Error('Program ID is not set');
     return new TransactionBuilder<null>(
@@ -312,6 +794,90 @@ export class Session {
     );
   }
 
+  public deleteSessionKey(key: ActorId): TransactionBuilder<null> {
+    if (!this._program.programId) throw new Error('Program ID is not set');
+    return new TransactionBuilder<null>(
+      this._program.api,
+      this._program.registry,
+      'send_message',
+      ['Session', 'DeleteSessionKey', key],
+      '(String, String, [u8;32])',
+      'Null',
+      this._program.programId,
+    );
+  }
+
+  public deleteSessionKeyFromProgram(session_for_account: ActorId, key: ActorId): TransactionBuilder<null> {
+    if (!this._program.programId) throw new Error('Program ID is not set');
+    return new TransactionBuilder<null>(
+      this._program.api,
+      this._program.registry,
+      'send_message',
+      ['Session', 'DeleteSessionKeyFromProgram', session_for_account, key],
+      '(String, String, [u8;32], [u8;32])',
+      'Null',
+      this._program.programId,
+    );
+  }
+
+  public extendSession(account: ActorId, key: ActorId, new_duration: number | string | bigint): TransactionBuilder<null> {
+    if (!this._program.programId) throw new Error('Program ID is not set');
+    return new TransactionBuilder<null>(
+      this._program.api,
+      this._program.registry,
+      'send_message',
+      ['Session', 'ExtendSession', account, key, new_duration],
+      '(String, String, [u8;32], [u8;32], u64)',
+      'Null',
+      this._program.programId,
+    );
+  }
+
+  public async activeSessionsOf(account: ActorId, originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<Array<SessionData>> {
+    const payload = this._program.registry.createType('(String, String, [u8;32])', ['Session', 'ActiveSessionsOf', account]).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, Vec<SessionData>)', reply.payload);
+    return result[2].toJSON() as Array<SessionData>;
+  }
+
+  public async remainingAllowance(account: ActorId, key: ActorId, originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<Array<SpendAllowance>> {
+    const payload = this._program.registry.createType('(String, String, [u8;32], [u8;32])', ['Session', 'RemainingAllowance', account, key]).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, Vec<SpendAllowance>)', reply.payload);
+    return result[2].toJSON() as Array<SpendAllowance>;
+  }
+
+  public async sessionActivity(account: ActorId, offset: number, limit: number, originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<Array<SessionActivity>> {
+    const payload = this._program.registry.createType('(String, String, [u8;32], u32, u32)', ['Session', 'SessionActivity', account, offset, limit]).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, Vec<SessionActivity>)', reply.payload);
+    return result[2].toJSON() as Array<SessionActivity>;
+  }
+
   public async sessionForTheAccount(account: ActorId, originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<SessionData | null> {
     const payload = this._program.registry.createType('(String, String, [u8;32])', ['Session', 'SessionForTheAccount', account]).toHex();
     const reply = await this._program.api.message.calculateReply({
@@ -327,6 +893,21 @@ export class Session {
     return result[2].toJSON() as SessionData | null;
   }
 
+  public async sessionForTheKey(account: ActorId, key: ActorId, originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<SessionData | null> {
+    const payload = this._program.registry.createType('(String, String, [u8;32], [u8;32])', ['Session', 'SessionForTheKey', account, key]).toHex();
+    const reply = await this._program.api.message.calculateReply({
+      destination: this._program.programId,
+      origin: originAddress ? decodeAddress(originAddress) : ZERO_ADDRESS,
+      payload,
+      value: value ?? 0,
+      gasLimit: this._program.api.blockGasLimit.toBigInt(),
+      at: atBlock,
+    });
+    if (!reply.code.isSuccess) throw new Error(this._program.registry.createType('String', reply.payload).toString());
+    const result = this._program.registry.createType('(String, String, Option<SessionData>)', reply.payload);
+    return result[2].toJSON() as SessionData | null;
+  }
+
   public async sessions(originAddress?: string, value?: number | string | bigint, atBlock?: `0x${string}`): Promise<Array<[ActorId, SessionData]>> {
     const payload = this._program.registry.createType('(String, String)', ['Session', 'Sessions']).toHex();
     const reply = await this._program.api.message.calculateReply({
@@ -342,22 +923,44 @@ export class Session {
     return result[2].toJSON() as Array<[ActorId, SessionData]>;
   }
 
-  public subscribeToSessionCreatedEvent(callback: (data: null) => void | Promise<void>): Promise<() => void> {
+  public subscribeToSessionCreatedEvent(callback: (data: { account: ActorId; key: ActorId; expires: number | string | bigint; allowed_actions: Array<ActionsForSession> }) => void | Promise<void>): Promise<() => void> {
     return this._program.api.gearEvents.subscribeToGearEvent('UserMessageSent', ({ data: { message } }) => {
       if (!message.source.eq(this._program.programId) || !message.destination.eq(ZERO_ADDRESS)) return;
       const payload = message.payload.toHex();
       if (getServiceNamePrefix(payload) === 'Session' && getFnNamePrefix(payload) === 'SessionCreated') {
-        void Promise.resolve(callback(null)).catch(console.error);
+        void Promise.resolve(
+          callback(
+            this._program.registry.createType('(String, String, {"account":"[u8;32]","key":"[u8;32]","expires":"u64","allowed_actions":"Vec<ActionsForSession>"})', message.payload)[2].toJSON() as { account: ActorId; key: ActorId; expires: number | string | bigint; allowed_actions: Array<ActionsForSession> }
+          )
+        ).catch(console.error);
       }
     });
   }
 
-  public subscribeToSessionDeletedEvent(callback: (data: null) => void | Promise<void>): Promise<() => void> {
+  public subscribeToSessionDeletedEvent(callback: (data: { account: ActorId; key: ActorId; reason: DeletionReason }) => void | Promise<void>): Promise<() => void> {
     return this._program.api.gearEvents.subscribeToGearEvent('UserMessageSent', ({ data: { message } }) => {
       if (!message.source.eq(this._program.programId) || !message.destination.eq(ZERO_ADDRESS)) return;
       const payload = message.payload.toHex();
       if (getServiceNamePrefix(payload) === 'Session' && getFnNamePrefix(payload) === 'SessionDeleted') {
-        void Promise.resolve(callback(null)).catch(console.error);
+        void Promise.resolve(
+          callback(
+            this._program.registry.createType('(String, String, {"account":"[u8;32]","key":"[u8;32]","reason":"DeletionReason"})', message.payload)[2].toJSON() as { account: ActorId; key: ActorId; reason: DeletionReason }
+          )
+        ).catch(console.error);
+      }
+    });
+  }
+
+  public subscribeToSessionExtendedEvent(callback: (data: { account: ActorId; key: ActorId; expires: number | string | bigint }) => void | Promise<void>): Promise<() => void> {
+    return this._program.api.gearEvents.subscribeToGearEvent('UserMessageSent', ({ data: { message } }) => {
+      if (!message.source.eq(this._program.programId) || !message.destination.eq(ZERO_ADDRESS)) return;
+      const payload = message.payload.toHex();
+      if (getServiceNamePrefix(payload) === 'Session' && getFnNamePrefix(payload) === 'SessionExtended') {
+        void Promise.resolve(
+          callback(
+            this._program.registry.createType('(String, String, {"account":"[u8;32]","key":"[u8;32]","expires":"u64"})', message.payload)[2].toJSON() as { account: ActorId; key: ActorId; expires: number | string | bigint }
+          )
+        ).catch(console.error);
       }
     });
   }
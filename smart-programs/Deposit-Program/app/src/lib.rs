@@ -3,7 +3,7 @@
 use sails_rs::prelude::*;
 pub mod services;
 use services::service::{Service, ActionsForSession};
-use session_service::*;
+use services::escrow::EscrowService;
 
 session_service::generate_session_system!(ActionsForSession);
 
@@ -13,16 +13,22 @@ pub struct Program;
 impl Program {
     pub fn new(config: Config) -> Self {
         Service::seed();
+        EscrowService::seed();
         SessionService::init(config);
         Self
     }
 
-    #[route("Service")]
+    #[export(route = "Service")]
     pub fn service(&self) -> Service {
         Service::new()
     }
 
-    #[route("Session")]
+    #[export(route = "Escrow")]
+    pub fn escrow(&self) -> EscrowService {
+        EscrowService::new()
+    }
+
+    #[export(route = "Session")]
     pub fn session(&self) -> SessionService {
         SessionService::new()
     }
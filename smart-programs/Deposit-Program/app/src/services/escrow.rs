@@ -0,0 +1,443 @@
+#![allow(static_mut_refs)]
+
+use sails_rs::{
+    prelude::*,
+    gstd::{msg, exec},
+    collections::{BTreeMap, BTreeSet, HashMap},
+};
+
+use super::migration::{VersionedState, STATE_VERSION};
+use super::service::{
+    charge_session, refund_session, ActionsForSession, ContractState, DepositError, OperationKind,
+};
+use super::token::{transfer_from_actor, transfer_to_actor};
+
+// Imports required for session signless
+use crate::{get_actor, record_session_call, refund_session_call, CallArgs};
+
+pub static mut ESCROW_STATE: Option<VersionedState<EscrowState>> = None;
+
+// Upper bound for a single page of `query_escrows_of`
+pub const MAX_ESCROW_PAGE_SIZE: u32 = 100;
+
+// Escrows by id. Funds are held by the program and counted in the token holdings
+// of `ContractState`, so `Service/Reconcile` also settles timed out escrow transfers.
+#[derive(Debug, Clone, Default)]
+pub struct EscrowState {
+    pub escrows: BTreeMap<u64, Escrow>,
+    // Escrow ids by payer, payee and arbiter
+    pub escrows_by_user: HashMap<ActorId, BTreeSet<u64>>,
+    pub next_escrow_id: u64,
+}
+
+impl EscrowState {
+    pub fn init_state() {
+        unsafe {
+            ESCROW_STATE = Some(VersionedState::new(STATE_VERSION, Self::default()));
+        }
+    }
+
+    pub fn state_mut() -> &'static mut EscrowState {
+        let state = unsafe { ESCROW_STATE.as_mut() };
+        debug_assert!(state.is_some(), "The state is not initialized");
+        unsafe { &mut state.unwrap_unchecked().state }
+    }
+
+    pub fn state_ref() -> &'static EscrowState {
+        let state = unsafe { ESCROW_STATE.as_ref() };
+        debug_assert!(state.is_some(), "The state is not initialized");
+        unsafe { &state.unwrap_unchecked().state }
+    }
+
+    pub fn insert_escrow(&mut self, escrow: Escrow) {
+        for user in [escrow.payer, escrow.payee, escrow.arbiter] {
+            self.escrows_by_user.entry(user).or_default().insert(escrow.escrow_id);
+        }
+        self.escrows.insert(escrow.escrow_id, escrow);
+    }
+
+    pub fn remove_escrow(&mut self, escrow_id: u64) {
+        let Some(escrow) = self.escrows.remove(&escrow_id) else {
+            return;
+        };
+        for user in [escrow.payer, escrow.payee, escrow.arbiter] {
+            if let Some(ids) = self.escrows_by_user.get_mut(&user) {
+                ids.remove(&escrow_id);
+                if ids.is_empty() {
+                    self.escrows_by_user.remove(&user);
+                }
+            }
+        }
+    }
+
+    // Activates a funded escrow, or drops it if the payer's transfer didn't happen
+    pub fn finish_funding(&mut self, escrow_id: u64, funded: bool) {
+        let Some(escrow) = self.escrows.get_mut(&escrow_id) else {
+            return;
+        };
+        if funded {
+            escrow.status = EscrowStatus::Active;
+            ContractState::state_mut().hold(escrow.token, escrow.amount);
+        } else {
+            self.remove_escrow(escrow_id);
+        }
+    }
+
+    // Whether `actor` may pay escrow `escrow_id` out with `status` (`Releasing` or `Refunding`)
+    pub fn check_payout(&self, actor: ActorId, escrow_id: u64, status: &EscrowStatus) -> Result<(), EscrowError> {
+        let escrow = self.escrows.get(&escrow_id).ok_or(EscrowError::UnknownEscrow)?;
+        if *status == EscrowStatus::Releasing {
+            if actor != escrow.payer && actor != escrow.arbiter {
+                return Err(EscrowError::NotAllowed);
+            }
+        } else if actor != escrow.arbiter {
+            if actor != escrow.payer {
+                return Err(EscrowError::NotAllowed);
+            }
+            if exec::block_timestamp() < escrow.deadline {
+                return Err(EscrowError::DeadlineNotReached);
+            }
+        }
+        if escrow.status != EscrowStatus::Active {
+            return Err(EscrowError::NotActive);
+        }
+        Ok(())
+    }
+
+    // Closes a paid out escrow, or reactivates it if the payout didn't happen
+    pub fn finish_payout(&mut self, escrow_id: u64, paid: bool) {
+        let Some(escrow) = self.escrows.get_mut(&escrow_id) else {
+            return;
+        };
+        escrow.status = match (escrow.status.clone(), paid) {
+            (EscrowStatus::Releasing, true) => EscrowStatus::Released,
+            (EscrowStatus::Refunding, true) => EscrowStatus::Refunded,
+            (status, true) => status,
+            (_, false) => {
+                ContractState::state_mut().hold(escrow.token, escrow.amount);
+                EscrowStatus::Active
+            }
+        };
+    }
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct Escrow {
+    pub escrow_id: u64,
+    pub payer: ActorId,
+    pub payee: ActorId,
+    pub arbiter: ActorId,
+    pub token: ActorId,
+    pub amount: u128,
+    // Block timestamp (ms) after which the payer can take the funds back
+    pub deadline: u64,
+    pub status: EscrowStatus,
+}
+
+// `Funding`, `Releasing` and `Refunding` wait for the token contract reply
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum EscrowStatus {
+    Funding,
+    Active,
+    Releasing,
+    Refunding,
+    Released,
+    Refunded,
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum EscrowError {
+    UnknownEscrow,
+    NotAllowed,
+    NotActive,
+    DeadlineNotReached,
+    OperationInProgress,
+    ReplyTimeout,
+    TransferFailed,
+}
+
+impl From<DepositError> for EscrowError {
+    fn from(error: DepositError) -> Self {
+        match error {
+            DepositError::OperationInProgress => EscrowError::OperationInProgress,
+            DepositError::ReplyTimeout => EscrowError::ReplyTimeout,
+            _ => EscrowError::TransferFailed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum EscrowEvents {
+    Created {
+        escrow_id: u64,
+        payer: ActorId,
+        payee: ActorId,
+        arbiter: ActorId,
+        token: ActorId,
+        amount: u128,
+        deadline: u64,
+        via_session: bool,
+    },
+    Released {
+        escrow_id: u64,
+        by: ActorId,
+        payee: ActorId,
+        amount: u128,
+    },
+    Refunded {
+        escrow_id: u64,
+        by: ActorId,
+        payer: ActorId,
+        amount: u128,
+    },
+    TransferTimedOut {
+        escrow_id: u64,
+        op_id: u64,
+    },
+}
+
+// Gives back the session call of a failed payout; a timed out one may still have paid
+fn refund_failed_call(error: &EscrowError, msg_src: &ActorId, session_for_account: &Option<ActorId>) {
+    if *error != EscrowError::ReplyTimeout {
+        refund_session_call(msg_src, session_for_account);
+    }
+}
+
+#[derive(Default)]
+pub struct EscrowService;
+
+impl EscrowService {
+    pub fn seed() {
+        EscrowState::init_state();
+    }
+
+    // Releases the actor lock after a token call; a timed out call keeps the
+    // escrow in its transitional status until `Service/Reconcile` settles it
+    fn finish_token_operation(
+        &mut self,
+        op_id: u64,
+        escrow_id: u64,
+        result: Result<(), DepositError>,
+    ) -> Result<(), EscrowError> {
+        let state = ContractState::state_mut();
+        if result == Err(DepositError::ReplyTimeout) {
+            state.time_out_operation(op_id);
+            self.emit_event(EscrowEvents::TransferTimedOut { escrow_id, op_id })
+                .expect("Event error");
+        } else {
+            state.finish_operation(op_id);
+        }
+        result.map_err(EscrowError::from)
+    }
+
+    // Pays the escrow out once `actor` is allowed to, see `EscrowState::check_payout`
+    async fn checked_pay_out(
+        &mut self,
+        actor: ActorId,
+        escrow_id: u64,
+        status: EscrowStatus,
+    ) -> Result<Escrow, EscrowError> {
+        EscrowState::state_ref().check_payout(actor, escrow_id, &status)?;
+        self.pay_out(actor, escrow_id, status).await
+    }
+
+    // Sends the escrowed funds to the payee (`Releasing`) or back to the payer (`Refunding`)
+    async fn pay_out(
+        &mut self,
+        actor: ActorId,
+        escrow_id: u64,
+        status: EscrowStatus,
+    ) -> Result<Escrow, EscrowError> {
+        let escrow = EscrowState::state_mut()
+            .escrows
+            .get_mut(&escrow_id)
+            .ok_or(EscrowError::UnknownEscrow)?;
+        let to = if status == EscrowStatus::Releasing {
+            escrow.payee
+        } else {
+            escrow.payer
+        };
+
+        let state = ContractState::state_mut();
+        let op_id = state.begin_operation(
+            actor,
+            escrow.token,
+            escrow.amount,
+            OperationKind::EscrowPayout { escrow_id },
+        )?;
+        state.release_hold(escrow.token, escrow.amount);
+        escrow.status = status;
+        let (token, amount) = (escrow.token, escrow.amount);
+
+        let transfer_result = transfer_to_actor(token, to, amount).await;
+        if let Err(e) = self.finish_token_operation(op_id, escrow_id, transfer_result) {
+            if e != EscrowError::ReplyTimeout {
+                EscrowState::state_mut().finish_payout(escrow_id, false);
+            }
+            return Err(e);
+        }
+
+        EscrowState::state_mut().finish_payout(escrow_id, true);
+        Ok(EscrowState::state_ref().escrows[&escrow_id].clone())
+    }
+}
+
+#[sails_rs::service(events = EscrowEvents)]
+impl EscrowService {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Locks the payer's tokens in a new escrow with signless/session support.
+    pub async fn create_escrow(
+        &mut self,
+        payee: ActorId,
+        arbiter: ActorId,
+        token_contract: ActorId,
+        amount: u128,
+        deadline: u64,
+        session_for_account: Option<ActorId>,
+    ) -> Result<EscrowEvents, EscrowError> {
+        ContractState::state_ref().assert_import_closed();
+        assert!(amount > 0, "Zero amount");
+        assert!(deadline > exec::block_timestamp(), "Deadline must be in the future");
+        let msg_src = msg::source();
+        let payer = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::CreateEscrow,
+            CallArgs::default()
+                .with_recipient(payee)
+                .with_token(token_contract)
+                .with_value(amount),
+        );
+
+        let escrow_state = EscrowState::state_mut();
+        let escrow_id = escrow_state.next_escrow_id;
+        let op_id = ContractState::state_mut().begin_operation(
+            payer,
+            token_contract,
+            amount,
+            OperationKind::EscrowFunding { escrow_id },
+        )
+        .inspect_err(|_| refund_session_call(&msg_src, &session_for_account))?;
+        charge_session(&session_for_account, token_contract, amount);
+        escrow_state.next_escrow_id = escrow_state.next_escrow_id.wrapping_add(1);
+        escrow_state.insert_escrow(Escrow {
+            escrow_id,
+            payer,
+            payee,
+            arbiter,
+            token: token_contract,
+            amount,
+            deadline,
+            status: EscrowStatus::Funding,
+        });
+
+        let transfer_result = transfer_from_actor(token_contract, payer, amount).await;
+        if let Err(e) = self.finish_token_operation(op_id, escrow_id, transfer_result) {
+            if e != EscrowError::ReplyTimeout {
+                EscrowState::state_mut().finish_funding(escrow_id, false);
+                refund_session(&session_for_account, token_contract, amount);
+                refund_session_call(&msg_src, &session_for_account);
+            }
+            return Err(e);
+        }
+        EscrowState::state_mut().finish_funding(escrow_id, true);
+
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::CreateEscrow);
+        let event = EscrowEvents::Created {
+            escrow_id,
+            payer,
+            payee,
+            arbiter,
+            token: token_contract,
+            amount,
+            deadline,
+            via_session: session_for_account.is_some(),
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        Ok(event)
+    }
+
+    /// Pays the escrow to the payee; the payer (also through a session) or the
+    /// arbiter can approve it.
+    pub async fn release_escrow(
+        &mut self,
+        escrow_id: u64,
+        session_for_account: Option<ActorId>,
+    ) -> Result<EscrowEvents, EscrowError> {
+        ContractState::state_ref().assert_import_closed();
+        let msg_src = msg::source();
+        let actor = get_actor(&msg_src, &session_for_account, ActionsForSession::ReleaseEscrow, CallArgs::default());
+
+        let escrow = self
+            .checked_pay_out(actor, escrow_id, EscrowStatus::Releasing)
+            .await
+            .inspect_err(|e| refund_failed_call(e, &msg_src, &session_for_account))?;
+
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::ReleaseEscrow);
+        let event = EscrowEvents::Released {
+            escrow_id,
+            by: actor,
+            payee: escrow.payee,
+            amount: escrow.amount,
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        Ok(event)
+    }
+
+    /// Returns the escrow to the payer; the arbiter can do it at any time and
+    /// the payer (also through a session) once the deadline has passed.
+    pub async fn refund_escrow(
+        &mut self,
+        escrow_id: u64,
+        session_for_account: Option<ActorId>,
+    ) -> Result<EscrowEvents, EscrowError> {
+        ContractState::state_ref().assert_import_closed();
+        let msg_src = msg::source();
+        let actor = get_actor(&msg_src, &session_for_account, ActionsForSession::RefundEscrow, CallArgs::default());
+
+        let escrow = self
+            .checked_pay_out(actor, escrow_id, EscrowStatus::Refunding)
+            .await
+            .inspect_err(|e| refund_failed_call(e, &msg_src, &session_for_account))?;
+
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::RefundEscrow);
+        let event = EscrowEvents::Refunded {
+            escrow_id,
+            by: actor,
+            payer: escrow.payer,
+            amount: escrow.amount,
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        Ok(event)
+    }
+
+    /// Returns an escrow by id (query)
+    pub fn query_escrow(&self, escrow_id: u64) -> Option<Escrow> {
+        EscrowState::state_ref().escrows.get(&escrow_id).cloned()
+    }
+
+    /// Returns a page of the escrows where the user is payer, payee or arbiter,
+    /// ordered by id (query)
+    pub fn query_escrows_of(&self, user: ActorId, offset: u32, limit: u32) -> Vec<Escrow> {
+        let state = EscrowState::state_ref();
+        let Some(ids) = state.escrows_by_user.get(&user) else {
+            return Vec::new();
+        };
+        ids.iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_ESCROW_PAGE_SIZE) as usize)
+            .map(|escrow_id| state.escrows[escrow_id].clone())
+            .collect()
+    }
+}
@@ -0,0 +1,14 @@
+pub use state_migration::{MigrationError, StateEnvelope, VersionedState, IMPORT_WINDOW_BLOCKS};
+use state_migration::Migration;
+
+// Layout version of the state written by this build. Bump it whenever the
+// state layout changes and register a migration from the previous version.
+pub const STATE_VERSION: u32 = 1;
+
+// Registered migrations as (version they upgrade from, migration)
+const MIGRATIONS: &[(u32, Migration)] = &[];
+
+// Brings an envelope written by any earlier build to STATE_VERSION
+pub fn migrate(envelope: StateEnvelope) -> Result<StateEnvelope, MigrationError> {
+    state_migration::migrate(envelope, STATE_VERSION, MIGRATIONS)
+}
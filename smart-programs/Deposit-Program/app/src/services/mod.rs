@@ -1 +1,6 @@
-pub mod service;
\ No newline at end of file
+pub mod service;
+pub mod rewards;
+pub mod token;
+pub mod escrow;
+pub mod migration;
+pub mod snapshot;
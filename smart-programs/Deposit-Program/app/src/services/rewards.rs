@@ -0,0 +1,77 @@
+use sails_rs::prelude::*;
+
+// Fixed-point scale of `acc_reward_per_share`
+pub const REWARD_PRECISION: u128 = 1_000_000_000_000;
+
+// Reward pool shared by everyone holding `stake_token` in the contract.
+// Each block releases `reward_per_block` from `available`, split by stake.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct RewardPool {
+    pub stake_token: ActorId,
+    pub reward_token: ActorId,
+    pub reward_per_block: u128,
+    // Funded rewards not yet released to stakers
+    pub available: u128,
+    pub total_staked: u128,
+    pub acc_reward_per_share: u128,
+    pub last_reward_block: u32,
+}
+
+impl RewardPool {
+    pub fn new(
+        stake_token: ActorId,
+        reward_token: ActorId,
+        reward_per_block: u128,
+        total_staked: u128,
+        current_block: u32,
+    ) -> Self {
+        Self {
+            stake_token,
+            reward_token,
+            reward_per_block,
+            available: 0,
+            total_staked,
+            acc_reward_per_share: 0,
+            last_reward_block: current_block,
+        }
+    }
+
+    // Releases the rewards of the blocks elapsed since the last update
+    pub fn accrue(&mut self, current_block: u32) {
+        if current_block <= self.last_reward_block {
+            return;
+        }
+        let blocks = u128::from(current_block - self.last_reward_block);
+        self.last_reward_block = current_block;
+        if self.total_staked == 0 {
+            return;
+        }
+
+        let reward = blocks.saturating_mul(self.reward_per_block).min(self.available);
+        self.available -= reward;
+        self.acc_reward_per_share = self
+            .acc_reward_per_share
+            .saturating_add(mul_div(reward, REWARD_PRECISION, self.total_staked));
+    }
+
+    // Rewards already accounted for a stake at the current accumulator
+    pub fn reward_debt_for(&self, stake: u128) -> u128 {
+        mul_div(stake, self.acc_reward_per_share, REWARD_PRECISION)
+    }
+
+    // Rewards earned by `stake` since `reward_debt` was recorded
+    pub fn earned(&self, stake: u128, reward_debt: u128) -> u128 {
+        self.reward_debt_for(stake).saturating_sub(reward_debt)
+    }
+}
+
+fn mul_div(a: u128, b: u128, c: u128) -> u128 {
+    let result = U256::from(a) * U256::from(b) / U256::from(c);
+    if result > U256::from(u128::MAX) {
+        u128::MAX
+    } else {
+        result.low_u128()
+    }
+}
@@ -1,53 +1,416 @@
 
-#![no_std]
 #![allow(static_mut_refs)]
 
 use sails_rs::{
     prelude::*,
     gstd::{msg, exec},
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap},
 };
-use extended_vft_client::vft::io as vft_io;
-use sails_rs::calls::ActionIo;
+
+use super::escrow::EscrowState;
+use super::migration::{MigrationError, StateEnvelope, VersionedState, IMPORT_WINDOW_BLOCKS, STATE_VERSION};
+use super::snapshot;
+use super::rewards::RewardPool;
+use super::token::{token_balance_of_program, transfer_from_actor, transfer_to_actor};
 
 // Imports required for session signless
-use crate::{SessionData, Storage};
+use crate::{get_actor, record_session_call, refund_session_call, CallArgs, Storage};
+
+pub static mut CONTRACT_STATE: Option<VersionedState<ContractState>> = None;
 
-pub static mut CONTRACT_STATE: Option<ContractState> = None;
+// Blocks to wait for a token contract reply before giving up on it
+pub const REPLY_TIMEOUT_BLOCKS: u32 = 100;
 
 // This struct holds balances per user and total received by the contract
 #[derive(Debug, Clone, Default)]
 pub struct ContractState {
-    pub user_balances: HashMap<ActorId, u128>,
+    pub admin: ActorId,
+    pub user_balances: BTreeMap<ActorId, u128>,
+    // Balances per (user, token contract)
+    pub token_balances: BTreeMap<(ActorId, ActorId), u128>,
     pub total_received: u128,
+    // Total credited per token contract, used to reconcile against the real balance
+    pub token_holdings: BTreeMap<ActorId, u128>,
+    // Operations awaiting a reply from a token contract, by operation id
+    pub pending_operations: HashMap<u64, PendingOperation>,
+    // Actors that currently have an operation in flight
+    pub actors_in_flight: HashMap<ActorId, u64>,
+    pub next_operation_id: u64,
+    // Operations whose token reply never arrived, waiting for `reconcile`
+    pub timed_out_operations: BTreeMap<u64, PendingOperation>,
+    // Timed out operations whose balance query is in flight, kept in `timed_out_operations` until it returns
+    pub reconciling: BTreeSet<u64>,
+    // Native VARA balances, kept apart from the token accounting
+    pub native_balances: BTreeMap<ActorId, u128>,
+    pub total_native: u128,
+    // Time-locked deposits per user
+    pub locks: BTreeMap<ActorId, Vec<LockEntry>>,
+    pub next_lock_id: u64,
+    // Staking rewards, once configured by the admin
+    pub rewards: Option<RewardPool>,
+    pub reward_debts: BTreeMap<ActorId, u128>,
+    pub unclaimed_rewards: BTreeMap<ActorId, u128>,
+    // `import_state` is accepted before this block; sealing moves it to the current block
+    pub import_open_until: u32,
 }
 
 impl ContractState {
     pub fn init_state() {
         unsafe {
-            CONTRACT_STATE = Some(Self::default());
+            CONTRACT_STATE = Some(VersionedState::new(STATE_VERSION, Self {
+                admin: msg::source(),
+                import_open_until: exec::block_height().saturating_add(IMPORT_WINDOW_BLOCKS),
+                ..Default::default()
+            }));
         }
     }
 
     pub fn state_mut() -> &'static mut ContractState {
         let state = unsafe { CONTRACT_STATE.as_mut() };
         debug_assert!(state.is_some(), "The state is not initialized");
-        unsafe { state.unwrap_unchecked() }
+        unsafe { &mut state.unwrap_unchecked().state }
     }
 
     pub fn state_ref() -> &'static ContractState {
         let state = unsafe { CONTRACT_STATE.as_ref() };
         debug_assert!(state.is_some(), "The state is not initialized");
-        unsafe { state.unwrap_unchecked() }
+        unsafe { &state.unwrap_unchecked().state }
+    }
+
+    pub fn version() -> u32 {
+        let state = unsafe { CONTRACT_STATE.as_ref() };
+        debug_assert!(state.is_some(), "The state is not initialized");
+        unsafe { state.unwrap_unchecked().version }
+    }
+
+    // Locks the actor until the operation is finished, rejecting overlapping calls
+    pub fn begin_operation(
+        &mut self,
+        actor: ActorId,
+        token: ActorId,
+        amount: u128,
+        kind: OperationKind,
+    ) -> Result<u64, DepositError> {
+        if self.actors_in_flight.contains_key(&actor) {
+            return Err(DepositError::OperationInProgress);
+        }
+
+        let op_id = self.next_operation_id;
+        self.next_operation_id = self.next_operation_id.wrapping_add(1);
+        self.actors_in_flight.insert(actor, op_id);
+        self.pending_operations.insert(
+            op_id,
+            PendingOperation {
+                op_id,
+                actor,
+                token,
+                amount,
+                kind,
+                started_at: exec::block_height(),
+            },
+        );
+        Ok(op_id)
+    }
+
+    // Releases the actor lock, returning the finished operation
+    pub fn finish_operation(&mut self, op_id: u64) -> Option<PendingOperation> {
+        let operation = self.pending_operations.remove(&op_id)?;
+        self.actors_in_flight.remove(&operation.actor);
+        Some(operation)
+    }
+
+    // Releases the actor lock and keeps the operation aside until it is reconciled
+    pub fn time_out_operation(&mut self, op_id: u64) -> Option<PendingOperation> {
+        let operation = self.finish_operation(op_id)?;
+        self.timed_out_operations.insert(op_id, operation.clone());
+        Some(operation)
+    }
+
+    // Credits a received token amount to the actor, returning the new token balance
+    pub fn credit(&mut self, actor: ActorId, token: ActorId, amount: u128) -> u128 {
+        if self.is_stake_token(token) {
+            self.settle_rewards(actor);
+        }
+
+        let entry = self.user_balances.entry(actor).or_default();
+        *entry = entry.saturating_add(amount);
+        let holdings = self.token_holdings.entry(token).or_default();
+        *holdings = holdings.saturating_add(amount);
+        self.total_received = self.total_received.saturating_add(amount);
+
+        let token_entry = self.token_balances.entry((actor, token)).or_default();
+        let old_balance = *token_entry;
+        *token_entry = token_entry.saturating_add(amount);
+        let new_balance = *token_entry;
+
+        self.update_stake(actor, token, old_balance, new_balance);
+        new_balance
+    }
+
+    // Counts tokens held by the contract outside user balances (e.g. escrows)
+    pub fn hold(&mut self, token: ActorId, amount: u128) {
+        let holdings = self.token_holdings.entry(token).or_default();
+        *holdings = holdings.saturating_add(amount);
+    }
+
+    pub fn release_hold(&mut self, token: ActorId, amount: u128) {
+        let holdings = self.token_holdings.entry(token).or_default();
+        *holdings = holdings.saturating_sub(amount);
+    }
+
+    // Moves credited balance between two users without touching the token contract,
+    // returning the new balances of both
+    pub fn move_balance(&mut self, from: ActorId, to: ActorId, token: ActorId, amount: u128) -> (u128, u128) {
+        let from_balance = self.token_balances.get(&(from, token)).copied().unwrap_or_default();
+        assert!(from_balance >= amount, "Insufficient balance");
+
+        if self.is_stake_token(token) {
+            self.settle_rewards(from);
+            self.settle_rewards(to);
+        }
+
+        let new_from_balance = from_balance - amount;
+        if new_from_balance == 0 {
+            self.token_balances.remove(&(from, token));
+        } else {
+            self.token_balances.insert((from, token), new_from_balance);
+        }
+        let from_total = self.user_balances.entry(from).or_default();
+        *from_total = from_total.saturating_sub(amount);
+        self.update_stake(from, token, from_balance, new_from_balance);
+
+        let to_entry = self.token_balances.entry((to, token)).or_default();
+        let to_balance = *to_entry;
+        *to_entry = to_entry.saturating_add(amount);
+        let new_to_balance = *to_entry;
+        let to_total = self.user_balances.entry(to).or_default();
+        *to_total = to_total.saturating_add(amount);
+        self.update_stake(to, token, to_balance, new_to_balance);
+
+        (new_from_balance, new_to_balance)
+    }
+
+    pub fn is_stake_token(&self, token: ActorId) -> bool {
+        self.rewards
+            .as_ref()
+            .is_some_and(|pool| pool.stake_token == token)
+    }
+
+    // Moves the rewards earned so far into `unclaimed_rewards`; call it before the stake changes
+    pub fn settle_rewards(&mut self, user: ActorId) {
+        let Some(pool) = self.rewards.as_mut() else {
+            return;
+        };
+        pool.accrue(exec::block_height());
+
+        let stake = self
+            .token_balances
+            .get(&(user, pool.stake_token))
+            .copied()
+            .unwrap_or_default();
+        let reward_debt = self.reward_debts.get(&user).copied().unwrap_or_default();
+        let earned = pool.earned(stake, reward_debt);
+        if earned > 0 {
+            let unclaimed = self.unclaimed_rewards.entry(user).or_default();
+            *unclaimed = unclaimed.saturating_add(earned);
+        }
+        self.reward_debts.insert(user, pool.reward_debt_for(stake));
+    }
+
+    // Keeps the reward pool in step with a change of the user's balance of `token`
+    pub fn update_stake(&mut self, user: ActorId, token: ActorId, old_balance: u128, new_balance: u128) {
+        let Some(pool) = self.rewards.as_mut() else {
+            return;
+        };
+        if pool.stake_token != token {
+            return;
+        }
+        pool.total_staked = pool
+            .total_staked
+            .saturating_sub(old_balance)
+            .saturating_add(new_balance);
+        self.reward_debts.insert(user, pool.reward_debt_for(new_balance));
+    }
+
+    // Adds funded rewards to the pool, releasing what was due before the funding
+    pub fn add_reward_funds(&mut self, amount: u128) {
+        let Some(pool) = self.rewards.as_mut() else {
+            return;
+        };
+        pool.accrue(exec::block_height());
+        pool.available = pool.available.saturating_add(amount);
+        let holdings = self.token_holdings.entry(pool.reward_token).or_default();
+        *holdings = holdings.saturating_add(amount);
+    }
+
+    // Gives back rewards taken for a claim whose transfer failed
+    pub fn restore_unclaimed(&mut self, user: ActorId, token: ActorId, amount: u128) {
+        let unclaimed = self.unclaimed_rewards.entry(user).or_default();
+        *unclaimed = unclaimed.saturating_add(amount);
+        let holdings = self.token_holdings.entry(token).or_default();
+        *holdings = holdings.saturating_add(amount);
+    }
+
+    // Records a time-locked deposit, returning the new lock entry
+    pub fn add_lock(&mut self, actor: ActorId, token: ActorId, amount: u128, unlock_at: u64) -> LockEntry {
+        let lock = LockEntry {
+            lock_id: self.next_lock_id,
+            token,
+            amount,
+            unlock_at,
+        };
+        self.next_lock_id = self.next_lock_id.wrapping_add(1);
+        self.restore_locks(actor, vec![lock.clone()]);
+        lock
+    }
+
+    // Removes the actor's locks for `token` that are unlocked at `now`
+    pub fn take_unlocked(&mut self, actor: ActorId, token: ActorId, now: u64) -> Vec<LockEntry> {
+        let Some(locks) = self.locks.get_mut(&actor) else {
+            return Vec::new();
+        };
+        let (unlocked, still_locked): (Vec<LockEntry>, Vec<LockEntry>) = locks
+            .drain(..)
+            .partition(|lock| lock.token == token && lock.unlock_at <= now);
+        *locks = still_locked;
+        if locks.is_empty() {
+            self.locks.remove(&actor);
+        }
+
+        let amount = unlocked.iter().map(|lock| lock.amount).sum::<u128>();
+        let holdings = self.token_holdings.entry(token).or_default();
+        *holdings = holdings.saturating_sub(amount);
+        unlocked
+    }
+
+    // Puts lock entries back, e.g. when sending the unlocked tokens failed
+    pub fn restore_locks(&mut self, actor: ActorId, locks: Vec<LockEntry>) {
+        for lock in &locks {
+            let holdings = self.token_holdings.entry(lock.token).or_default();
+            *holdings = holdings.saturating_add(lock.amount);
+        }
+        let entries = self.locks.entry(actor).or_default();
+        entries.extend(locks);
+        entries.sort_unstable_by_key(|lock| lock.lock_id);
+    }
+
+    pub fn has_operation_in_flight_for(&self, token: ActorId) -> bool {
+        self.pending_operations
+            .values()
+            .any(|operation| operation.token == token)
+    }
+
+    // Operations wait for the import window to close, so imported entries can't overwrite them
+    pub fn assert_import_closed(&self) {
+        assert!(exec::block_height() >= self.import_open_until, "State import is still open");
+    }
+
+    // Whether another timed out operation on `token` could also be reflected in its balance
+    pub fn has_other_timed_out_operation_for(&self, op_id: u64, token: ActorId) -> bool {
+        self.timed_out_operations
+            .values()
+            .any(|operation| operation.op_id != op_id && operation.token == token)
+    }
+
+    // Whether the balance of `token` can tell if timed out operation `op_id` went through
+    pub fn check_reconcilable(&self, op_id: u64, token: ActorId) -> Result<(), DepositError> {
+        // A deposit still in flight could already be reflected in the balance
+        if self.has_operation_in_flight_for(token) {
+            return Err(DepositError::OperationInProgress);
+        }
+        if self.has_other_timed_out_operation_for(op_id, token) {
+            return Err(DepositError::AmbiguousReconciliation);
+        }
+        Ok(())
+    }
+}
+
+// An operation that has sent a message to a token contract and awaits its reply
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct PendingOperation {
+    pub op_id: u64,
+    pub actor: ActorId,
+    pub token: ActorId,
+    pub amount: u128,
+    pub kind: OperationKind,
+    pub started_at: u32,
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum OperationKind {
+    Deposit,
+    DepositLocked { unlock_at: u64 },
+    // Tokens sent back to the user; holds the locks to restore if it fails
+    ClaimUnlocked { locks: Vec<LockEntry> },
+    FundRewards,
+    ClaimRewards,
+    EscrowFunding { escrow_id: u64 },
+    EscrowPayout { escrow_id: u64 },
+}
+
+impl OperationKind {
+    // Whether the operation moves tokens into this contract
+    pub fn is_incoming(&self) -> bool {
+        !matches!(
+            self,
+            OperationKind::ClaimUnlocked { .. }
+                | OperationKind::ClaimRewards
+                | OperationKind::EscrowPayout { .. }
+        )
     }
 }
 
+// A deposit that can't be claimed before `unlock_at` (block timestamp, ms)
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct LockEntry {
+    pub lock_id: u64,
+    pub token: ActorId,
+    pub amount: u128,
+    pub unlock_at: u64,
+}
+
+// Errors returned by calls that wait on a token contract or send value. They are
+// returned instead of panicking because a panic after the reply would not roll
+// back the state saved before awaiting (e.g. the actor lock).
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum DepositError {
+    OperationInProgress,
+    SendFailed,
+    TransferFailed,
+    ReplyTimeout,
+    UnknownOperation,
+    BalanceQueryFailed,
+    // Several timed out operations share the token, the balance can't tell them apart
+    AmbiguousReconciliation,
+    NothingToClaim,
+    RewardsNotConfigured,
+    // The withdrawal or the balance it leaves is below the existential deposit
+    BelowExistentialDeposit,
+}
+
 // For session-based calls/actions
 #[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
 #[codec(crate = sails_rs::scale_codec)]
 #[scale_info(crate = sails_rs::scale_info)]
 pub enum ActionsForSession {
     Deposit,
+    DepositNative,
+    WithdrawNative,
+    DepositLocked,
+    ClaimUnlocked,
+    ClaimRewards,
+    InternalTransfer,
+    CreateEscrow,
+    ReleaseEscrow,
+    RefundEscrow,
 }
 
 // Used for program events
@@ -55,7 +418,80 @@ pub enum ActionsForSession {
 #[codec(crate = sails_rs::scale_codec)]
 #[scale_info(crate = sails_rs::scale_info)]
 pub enum Events {
-    Deposited(ActorId, u128),
+    Deposited {
+        actor: ActorId,
+        token: ActorId,
+        amount: u128,
+        new_balance: u128,
+        via_session: bool,
+    },
+    OperationTimedOut {
+        op_id: u64,
+        actor: ActorId,
+        token: ActorId,
+        amount: u128,
+    },
+    OperationReconciled {
+        op_id: u64,
+        actor: ActorId,
+        token: ActorId,
+        amount: u128,
+        settled: bool,
+    },
+    Locked {
+        actor: ActorId,
+        token: ActorId,
+        amount: u128,
+        lock_id: u64,
+        unlock_at: u64,
+        via_session: bool,
+    },
+    UnlockedClaimed {
+        actor: ActorId,
+        token: ActorId,
+        amount: u128,
+        via_session: bool,
+    },
+    InternalTransferred {
+        from: ActorId,
+        to: ActorId,
+        token: ActorId,
+        amount: u128,
+        from_balance: u128,
+        to_balance: u128,
+        via_session: bool,
+    },
+    RewardsConfigured {
+        stake_token: ActorId,
+        reward_token: ActorId,
+        reward_per_block: u128,
+    },
+    RewardsFunded {
+        amount: u128,
+        available: u128,
+    },
+    RewardsClaimed {
+        actor: ActorId,
+        token: ActorId,
+        amount: u128,
+        via_session: bool,
+    },
+    StateImported {
+        entries: u32,
+    },
+    StateSealed,
+    NativeDeposited {
+        actor: ActorId,
+        amount: u128,
+        new_balance: u128,
+        via_session: bool,
+    },
+    NativeWithdrawn {
+        actor: ActorId,
+        amount: u128,
+        new_balance: u128,
+        via_session: bool,
+    },
 }
 
 #[derive(Debug, Clone, Encode, Decode, TypeInfo, Default)]
@@ -63,14 +499,20 @@ pub enum Events {
 #[scale_info(crate = sails_rs::scale_info)]
 pub struct IoContractState {
     pub user_balances: Vec<(ActorId, u128)>,
+    pub token_balances: Vec<((ActorId, ActorId), u128)>,
     pub total_received: u128,
+    pub native_balances: Vec<(ActorId, u128)>,
+    pub total_native: u128,
 }
 
 impl From<ContractState> for IoContractState {
     fn from(state: ContractState) -> Self {
         Self {
             user_balances: state.user_balances.iter().map(|(k, v)| (*k, *v)).collect(),
+            token_balances: state.token_balances.iter().map(|(k, v)| (*k, *v)).collect(),
             total_received: state.total_received,
+            native_balances: state.native_balances.iter().map(|(k, v)| (*k, *v)).collect(),
+            total_native: state.total_native,
         }
     }
 }
@@ -82,35 +524,81 @@ impl Service {
     pub fn seed() {
         ContractState::init_state();
     }
+
+    // Releases the actor lock after a token call, recording the operation if the reply timed out
+    fn finish_token_operation(
+        &mut self,
+        op_id: u64,
+        result: Result<(), DepositError>,
+    ) -> Result<(), DepositError> {
+        let state = ContractState::state_mut();
+        if result == Err(DepositError::ReplyTimeout) {
+            if let Some(operation) = state.time_out_operation(op_id) {
+                self.emit_event(Events::OperationTimedOut {
+                    op_id,
+                    actor: operation.actor,
+                    token: operation.token,
+                    amount: operation.amount,
+                })
+                .expect("Event error");
+            }
+        } else {
+            state.finish_operation(op_id);
+        }
+        result
+    }
+
+    // Applies the outcome of a timed out operation and emits OperationReconciled
+    fn apply_reconciliation(&mut self, op_id: u64, operation: PendingOperation, settled: bool) -> Events {
+        let state = ContractState::state_mut();
+        match (operation.kind, settled) {
+            (OperationKind::Deposit, true) => {
+                state.credit(operation.actor, operation.token, operation.amount);
+            }
+            (OperationKind::DepositLocked { unlock_at }, true) => {
+                state.add_lock(operation.actor, operation.token, operation.amount, unlock_at);
+            }
+            (OperationKind::ClaimUnlocked { locks }, false) => {
+                state.restore_locks(operation.actor, locks);
+            }
+            (OperationKind::FundRewards, true) => {
+                state.add_reward_funds(operation.amount);
+            }
+            (OperationKind::ClaimRewards, false) => {
+                state.restore_unclaimed(operation.actor, operation.token, operation.amount);
+            }
+            (OperationKind::EscrowFunding { escrow_id }, funded) => {
+                EscrowState::state_mut().finish_funding(escrow_id, funded);
+            }
+            (OperationKind::EscrowPayout { escrow_id }, paid) => {
+                EscrowState::state_mut().finish_payout(escrow_id, paid);
+            }
+            _ => {}
+        }
+
+        let event = Events::OperationReconciled {
+            op_id,
+            actor: operation.actor,
+            token: operation.token,
+            amount: operation.amount,
+            settled,
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        event
+    }
 }
 
-// Helper for session verification; returns correct actor for sender or session flow
-fn get_actor(
-    session_map: &HashMap<ActorId, SessionData>,
-    msg_source: &ActorId,
-    session_for_account: &Option<ActorId>,
-    action: ActionsForSession,
-) -> ActorId {
-    match session_for_account {
-        Some(account) => {
-            let session = session_map
-                .get(account)
-                .expect("No valid session for this account");
-            assert!(
-                session.expires > exec::block_timestamp(),
-                "Session expired"
-            );
-            assert!(
-                session.allowed_actions.contains(&action),
-                "Action not allowed"
-            );
-            assert_eq!(
-                session.key, *msg_source,
-                "Sender not authorized for session"
-            );
-            *account
-        }
-        None => *msg_source,
+// Charges a session call against the spend limits of the sender's session key
+pub(crate) fn charge_session(session_for_account: &Option<ActorId>, token: ActorId, amount: u128) {
+    if let Some(account) = session_for_account {
+        Storage::spend(account, &msg::source(), token, amount);
+    }
+}
+
+// Gives back a session charge when the tokens were not moved
+pub(crate) fn refund_session(session_for_account: &Option<ActorId>, token: ActorId, amount: u128) {
+    if let Some(account) = session_for_account {
+        Storage::refund(account, &msg::source(), token, amount);
     }
 }
 
@@ -121,57 +609,616 @@ impl Service {
     }
 
     /// Transfers FT tokens from a user to this contract with signless/session support.
-   pub async fn deposit(
-    &mut self,
-    token_contract: ActorId,
-    amount: u128,
-    session_for_account: Option<ActorId>,
-) -> Events {
-    assert!(amount > 0, "Zero amount");
-    let msg_src = msg::source();
-    let sessions = Storage::get_session_map();
-    let actor = get_actor(&sessions, &msg_src, &session_for_account, ActionsForSession::Deposit);
-
-    let amount_u256 = U256::from(amount);
-
-    let request = vft_io::TransferFrom::encode_call(actor, exec::program_id(), amount_u256);
-
-    let send_result = msg::send_bytes_with_gas_for_reply(token_contract, request, 5_000_000_000, 0, 0);
-
-    match send_result {
-        Ok(reply_future) => match reply_future.await {
-            Ok(reply) => {
-               
+    pub async fn deposit(
+        &mut self,
+        token_contract: ActorId,
+        amount: u128,
+        session_for_account: Option<ActorId>,
+    ) -> Result<Events, DepositError> {
+        ContractState::state_ref().assert_import_closed();
+        assert!(amount > 0, "Zero amount");
+        let msg_src = msg::source();
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::Deposit,
+            CallArgs::default().with_token(token_contract).with_value(amount),
+        );
+
+        let op_id = ContractState::state_mut().begin_operation(
+            actor,
+            token_contract,
+            amount,
+            OperationKind::Deposit,
+        )
+        .inspect_err(|_| refund_session_call(&msg_src, &session_for_account))?;
+        charge_session(&session_for_account, token_contract, amount);
+
+        let transfer_result = transfer_from_actor(token_contract, actor, amount).await;
+        if let Err(e) = self.finish_token_operation(op_id, transfer_result) {
+            if e != DepositError::ReplyTimeout {
+                refund_session(&session_for_account, token_contract, amount);
+                refund_session_call(&msg_src, &session_for_account);
             }
-            Err(e) => {
-                panic!("TransferFrom did not reply successfully: {:?}", e);
+            return Err(e);
+        }
+
+        let state = ContractState::state_mut();
+
+        // Actualiza el estado para actor y contrato
+        let new_balance = state.credit(actor, token_contract, amount);
+
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::Deposit);
+        let event = Events::Deposited {
+            actor,
+            token: token_contract,
+            amount,
+            new_balance,
+            via_session: session_for_account.is_some(),
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        Ok(event)
+    }
+
+    /// Moves deposited tokens to another user inside the contract, with
+    /// signless/session support.
+    pub fn internal_transfer(
+        &mut self,
+        to: ActorId,
+        token_contract: ActorId,
+        amount: u128,
+        session_for_account: Option<ActorId>,
+    ) -> Events {
+        ContractState::state_ref().assert_import_closed();
+        assert!(amount > 0, "Zero amount");
+        let msg_src = msg::source();
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::InternalTransfer,
+            CallArgs::default()
+                .with_recipient(to)
+                .with_token(token_contract)
+                .with_value(amount),
+        );
+        assert_ne!(actor, to, "Cannot transfer to yourself");
+        charge_session(&session_for_account, token_contract, amount);
+
+        let (from_balance, to_balance) =
+            ContractState::state_mut().move_balance(actor, to, token_contract, amount);
+
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::InternalTransfer);
+        let event = Events::InternalTransferred {
+            from: actor,
+            to,
+            token: token_contract,
+            amount,
+            from_balance,
+            to_balance,
+            via_session: session_for_account.is_some(),
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        event
+    }
+
+    /// Credits the attached VARA to the user with signless/session support.
+    pub fn deposit_native(&mut self, session_for_account: Option<ActorId>) -> Events {
+        ContractState::state_ref().assert_import_closed();
+        let amount = msg::value();
+        assert!(amount > 0, "Zero amount");
+        let msg_src = msg::source();
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::DepositNative,
+            CallArgs::default().with_value(amount),
+        );
+        // Native VARA is charged under the zero address
+        charge_session(&session_for_account, ActorId::zero(), amount);
+
+        let state = ContractState::state_mut();
+        let entry = state.native_balances.entry(actor).or_default();
+        *entry = entry.saturating_add(amount);
+        let new_balance = *entry;
+        state.total_native = state.total_native.saturating_add(amount);
+
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::DepositNative);
+        let event = Events::NativeDeposited {
+            actor,
+            amount,
+            new_balance,
+            via_session: session_for_account.is_some(),
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        event
+    }
+
+    /// Sends deposited VARA back to the user with signless/session support.
+    /// Both the amount and what is left must be zero or reach the existential
+    /// deposit, otherwise the value could not be sent or the rest withdrawn.
+    pub fn withdraw_native(
+        &mut self,
+        amount: u128,
+        session_for_account: Option<ActorId>,
+    ) -> Result<Events, DepositError> {
+        ContractState::state_ref().assert_import_closed();
+        assert!(amount > 0, "Zero amount");
+        let msg_src = msg::source();
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::WithdrawNative,
+            CallArgs::default().with_value(amount),
+        );
+
+        let state = ContractState::state_mut();
+        let balance = state.native_balances.get(&actor).copied().unwrap_or_default();
+        assert!(balance >= amount, "Insufficient balance");
+        let new_balance = balance - amount;
+        let existential_deposit = exec::env_vars().existential_deposit;
+        if amount < existential_deposit || (new_balance != 0 && new_balance < existential_deposit) {
+            refund_session_call(&msg_src, &session_for_account);
+            return Err(DepositError::BelowExistentialDeposit);
+        }
+
+        charge_session(&session_for_account, ActorId::zero(), amount);
+        // Value always goes to the account, never to the session key
+        if msg::send_bytes(actor, [], amount).is_err() {
+            refund_session(&session_for_account, ActorId::zero(), amount);
+            refund_session_call(&msg_src, &session_for_account);
+            return Err(DepositError::SendFailed);
+        }
+
+        if new_balance == 0 {
+            state.native_balances.remove(&actor);
+        } else {
+            state.native_balances.insert(actor, new_balance);
+        }
+        state.total_native = state.total_native.saturating_sub(amount);
+
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::WithdrawNative);
+        let event = Events::NativeWithdrawn {
+            actor,
+            amount,
+            new_balance,
+            via_session: session_for_account.is_some(),
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        Ok(event)
+    }
+
+    /// Transfers FT tokens that can't be claimed back before `unlock_at`
+    /// (block timestamp, ms), with signless/session support.
+    pub async fn deposit_locked(
+        &mut self,
+        token_contract: ActorId,
+        amount: u128,
+        unlock_at: u64,
+        session_for_account: Option<ActorId>,
+    ) -> Result<Events, DepositError> {
+        ContractState::state_ref().assert_import_closed();
+        assert!(amount > 0, "Zero amount");
+        assert!(unlock_at > exec::block_timestamp(), "Unlock time must be in the future");
+        let msg_src = msg::source();
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::DepositLocked,
+            CallArgs::default().with_token(token_contract).with_value(amount),
+        );
+
+        let op_id = ContractState::state_mut().begin_operation(
+            actor,
+            token_contract,
+            amount,
+            OperationKind::DepositLocked { unlock_at },
+        )
+        .inspect_err(|_| refund_session_call(&msg_src, &session_for_account))?;
+        charge_session(&session_for_account, token_contract, amount);
+
+        let transfer_result = transfer_from_actor(token_contract, actor, amount).await;
+        if let Err(e) = self.finish_token_operation(op_id, transfer_result) {
+            if e != DepositError::ReplyTimeout {
+                refund_session(&session_for_account, token_contract, amount);
+                refund_session_call(&msg_src, &session_for_account);
             }
-        },
-        Err(e) => {
-            panic!("Send TransferFrom failed: {:?}", e);
+            return Err(e);
         }
+
+        let lock = ContractState::state_mut().add_lock(actor, token_contract, amount, unlock_at);
+
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::DepositLocked);
+        let event = Events::Locked {
+            actor,
+            token: token_contract,
+            amount,
+            lock_id: lock.lock_id,
+            unlock_at,
+            via_session: session_for_account.is_some(),
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        Ok(event)
     }
 
-    // Actualiza el estado para actor y contrato
-    let state = ContractState::state_mut();
-    let entry = state.user_balances.entry(actor).or_default();
-    *entry = entry.saturating_add(amount);
-    state.total_received = state.total_received.saturating_add(amount);
+    /// Sends every unlocked deposit of `token_contract` back to the user,
+    /// with signless/session support.
+    pub async fn claim_unlocked(
+        &mut self,
+        token_contract: ActorId,
+        session_for_account: Option<ActorId>,
+    ) -> Result<Events, DepositError> {
+        ContractState::state_ref().assert_import_closed();
+        let msg_src = msg::source();
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::ClaimUnlocked,
+            CallArgs::default().with_token(token_contract),
+        );
 
-    self.emit_event(Events::Deposited(actor, amount)).expect("Event error");
-    Events::Deposited(actor, amount)
-}
+        let state = ContractState::state_mut();
+        if state.actors_in_flight.contains_key(&actor) {
+            refund_session_call(&msg_src, &session_for_account);
+            return Err(DepositError::OperationInProgress);
+        }
+        let locks = state.take_unlocked(actor, token_contract, exec::block_timestamp());
+        if locks.is_empty() {
+            refund_session_call(&msg_src, &session_for_account);
+            return Err(DepositError::NothingToClaim);
+        }
+        let amount = locks.iter().map(|lock| lock.amount).sum::<u128>();
+        let op_id = state.begin_operation(
+            actor,
+            token_contract,
+            amount,
+            OperationKind::ClaimUnlocked { locks: locks.clone() },
+        )?;
+
+        let transfer_result = transfer_to_actor(token_contract, actor, amount).await;
+        if let Err(e) = self.finish_token_operation(op_id, transfer_result) {
+            // A timed out claim keeps its locks in the operation until reconciled
+            if e != DepositError::ReplyTimeout {
+                ContractState::state_mut().restore_locks(actor, locks);
+                refund_session_call(&msg_src, &session_for_account);
+            }
+            return Err(e);
+        }
+
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::ClaimUnlocked);
+        let event = Events::UnlockedClaimed {
+            actor,
+            token: token_contract,
+            amount,
+            via_session: session_for_account.is_some(),
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        Ok(event)
+    }
+
+    /// Sets up the reward pool for depositors of `stake_token` (admin only).
+    pub fn configure_rewards(
+        &mut self,
+        stake_token: ActorId,
+        reward_token: ActorId,
+        reward_per_block: u128,
+    ) -> Events {
+        ContractState::state_ref().assert_import_closed();
+        let state = ContractState::state_mut();
+        assert_eq!(state.admin, msg::source(), "Not admin");
+        assert!(state.rewards.is_none(), "Rewards already configured");
+
+        let total_staked = state
+            .token_balances
+            .iter()
+            .filter(|((_, token), _)| *token == stake_token)
+            .map(|(_, balance)| *balance)
+            .fold(0u128, u128::saturating_add);
+        state.rewards = Some(RewardPool::new(
+            stake_token,
+            reward_token,
+            reward_per_block,
+            total_staked,
+            exec::block_height(),
+        ));
+
+        let event = Events::RewardsConfigured {
+            stake_token,
+            reward_token,
+            reward_per_block,
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        event
+    }
+
+    /// Changes the rewards released per block (admin only).
+    pub fn set_reward_rate(&mut self, reward_per_block: u128) -> Events {
+        ContractState::state_ref().assert_import_closed();
+        let state = ContractState::state_mut();
+        assert_eq!(state.admin, msg::source(), "Not admin");
+        let pool = state.rewards.as_mut().expect("Rewards not configured");
+        pool.accrue(exec::block_height());
+        pool.reward_per_block = reward_per_block;
+
+        let event = Events::RewardsConfigured {
+            stake_token: pool.stake_token,
+            reward_token: pool.reward_token,
+            reward_per_block,
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        event
+    }
+
+    /// Transfers reward tokens from the admin into the reward pool (admin only).
+    pub async fn fund_rewards(&mut self, amount: u128) -> Result<Events, DepositError> {
+        ContractState::state_ref().assert_import_closed();
+        assert!(amount > 0, "Zero amount");
+        let state = ContractState::state_mut();
+        let admin = msg::source();
+        assert_eq!(state.admin, admin, "Not admin");
+        let reward_token = state
+            .rewards
+            .as_ref()
+            .ok_or(DepositError::RewardsNotConfigured)?
+            .reward_token;
+
+        let op_id = state.begin_operation(admin, reward_token, amount, OperationKind::FundRewards)?;
+
+        let transfer_result = transfer_from_actor(reward_token, admin, amount).await;
+        self.finish_token_operation(op_id, transfer_result)?;
+
+        let state = ContractState::state_mut();
+        state.add_reward_funds(amount);
+        let available = state.rewards.as_ref().map(|pool| pool.available).unwrap_or_default();
+
+        let event = Events::RewardsFunded { amount, available };
+        self.emit_event(event.clone()).expect("Event error");
+        Ok(event)
+    }
+
+    /// Sends the accrued rewards to the user with signless/session support.
+    pub async fn claim_rewards(&mut self, session_for_account: Option<ActorId>) -> Result<Events, DepositError> {
+        ContractState::state_ref().assert_import_closed();
+        let msg_src = msg::source();
+        let actor = get_actor(&msg_src, &session_for_account, ActionsForSession::ClaimRewards, CallArgs::default());
+
+        let state = ContractState::state_mut();
+        let Some(pool) = state.rewards.as_ref() else {
+            refund_session_call(&msg_src, &session_for_account);
+            return Err(DepositError::RewardsNotConfigured);
+        };
+        let reward_token = pool.reward_token;
+        if state.actors_in_flight.contains_key(&actor) {
+            refund_session_call(&msg_src, &session_for_account);
+            return Err(DepositError::OperationInProgress);
+        }
+
+        state.settle_rewards(actor);
+        let amount = state.unclaimed_rewards.remove(&actor).unwrap_or_default();
+        if amount == 0 {
+            refund_session_call(&msg_src, &session_for_account);
+            return Err(DepositError::NothingToClaim);
+        }
+        let holdings = state.token_holdings.entry(reward_token).or_default();
+        *holdings = holdings.saturating_sub(amount);
+        let op_id = state.begin_operation(actor, reward_token, amount, OperationKind::ClaimRewards)?;
+
+        let transfer_result = transfer_to_actor(reward_token, actor, amount).await;
+        if let Err(e) = self.finish_token_operation(op_id, transfer_result) {
+            // A timed out claim is restored by `reconcile` if the transfer didn't happen
+            if e != DepositError::ReplyTimeout {
+                ContractState::state_mut().restore_unclaimed(actor, reward_token, amount);
+                refund_session_call(&msg_src, &session_for_account);
+            }
+            return Err(e);
+        }
+
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::ClaimRewards);
+        let event = Events::RewardsClaimed {
+            actor,
+            token: reward_token,
+            amount,
+            via_session: session_for_account.is_some(),
+        };
+        self.emit_event(event.clone()).expect("Event error");
+        Ok(event)
+    }
+
+    /// Settles or cancels a timed out operation by checking the token balance
+    /// held by this contract (admin only). Refused while another timed out
+    /// operation on the same token is unresolved, see `settle_operation`.
+    pub async fn reconcile(&mut self, op_id: u64) -> Result<Events, DepositError> {
+        ContractState::state_ref().assert_import_closed();
+        let state = ContractState::state_mut();
+        assert_eq!(state.admin, msg::source(), "Not admin");
+
+        let operation = state
+            .timed_out_operations
+            .get(&op_id)
+            .cloned()
+            .ok_or(DepositError::UnknownOperation)?;
+        if state.reconciling.contains(&op_id) {
+            return Err(DepositError::OperationInProgress);
+        }
+        state.check_reconcilable(op_id, operation.token)?;
+
+        // The operation stays timed out while the query is in flight, so another
+        // reconcile on the same token sees it and is refused as ambiguous
+        state.reconciling.insert(op_id);
+        let balance_result = token_balance_of_program(operation.token).await;
+
+        let state = ContractState::state_mut();
+        state.reconciling.remove(&op_id);
+        let balance = balance_result?;
+        // Operations started on the token meanwhile may already be in the balance
+        state.check_reconcilable(op_id, operation.token)?;
+        state.timed_out_operations.remove(&op_id);
+
+        let held = state
+            .token_holdings
+            .get(&operation.token)
+            .copied()
+            .unwrap_or_default();
+        // Incoming tokens settled if the balance covers them; outgoing ones if it doesn't
+        let covered = balance >= held.saturating_add(operation.amount);
+        let settled = covered == operation.kind.is_incoming();
+        Ok(self.apply_reconciliation(op_id, operation, settled))
+    }
+
+    /// Settles or cancels a timed out operation as checked by the admin off-chain,
+    /// for operations `reconcile` can't resolve (admin only).
+    pub fn settle_operation(&mut self, op_id: u64, settled: bool) -> Result<Events, DepositError> {
+        ContractState::state_ref().assert_import_closed();
+        let state = ContractState::state_mut();
+        assert_eq!(state.admin, msg::source(), "Not admin");
+
+        // `reconcile` is waiting on the balance of this operation's token
+        if state.reconciling.contains(&op_id) {
+            return Err(DepositError::OperationInProgress);
+        }
+        let operation = state
+            .timed_out_operations
+            .remove(&op_id)
+            .ok_or(DepositError::UnknownOperation)?;
+        Ok(self.apply_reconciliation(op_id, operation, settled))
+    }
 
     /// Returns the balance of a given user (query)
     pub fn query_user_balance(&self, user: ActorId) -> u128 {
         ContractState::state_ref().user_balances.get(&user).cloned().unwrap_or_default()
     }
 
+    /// Returns the balance of a given user for a specific token contract (query)
+    pub fn query_user_token_balance(&self, user: ActorId, token_contract: ActorId) -> u128 {
+        ContractState::state_ref()
+            .token_balances
+            .get(&(user, token_contract))
+            .cloned()
+            .unwrap_or_default()
+    }
+
     /// Returns the total amount received by the contract (query)
     pub fn query_total_received(&self) -> u128 {
         ContractState::state_ref().total_received
     }
 
+    /// Returns the operations still waiting for a token reply, ordered by id (query)
+    pub fn query_pending_operations(&self) -> Vec<PendingOperation> {
+        let mut operations: Vec<PendingOperation> = ContractState::state_ref()
+            .pending_operations
+            .values()
+            .cloned()
+            .collect();
+        operations.sort_unstable_by_key(|operation| operation.op_id);
+        operations
+    }
+
+    /// Returns the in-flight operation of a given actor, if any (query)
+    pub fn query_pending_operation_of(&self, actor: ActorId) -> Option<PendingOperation> {
+        let state = ContractState::state_ref();
+        state
+            .actors_in_flight
+            .get(&actor)
+            .and_then(|op_id| state.pending_operations.get(op_id))
+            .cloned()
+    }
+
+    /// Returns the rewards a user could claim right now (query)
+    pub fn query_pending_rewards(&self, user: ActorId) -> u128 {
+        let state = ContractState::state_ref();
+        let Some(pool) = state.rewards.as_ref() else {
+            return 0;
+        };
+        let mut pool = pool.clone();
+        pool.accrue(exec::block_height());
+
+        let stake = state
+            .token_balances
+            .get(&(user, pool.stake_token))
+            .copied()
+            .unwrap_or_default();
+        let reward_debt = state.reward_debts.get(&user).copied().unwrap_or_default();
+        let unclaimed = state.unclaimed_rewards.get(&user).copied().unwrap_or_default();
+        unclaimed.saturating_add(pool.earned(stake, reward_debt))
+    }
+
+    /// Returns the reward pool configuration and accumulators (query)
+    pub fn query_reward_pool(&self) -> Option<RewardPool> {
+        ContractState::state_ref().rewards.clone()
+    }
+
+    /// Returns the time-locked deposits of a given user (query)
+    pub fn query_user_locks(&self, user: ActorId) -> Vec<LockEntry> {
+        ContractState::state_ref().locks.get(&user).cloned().unwrap_or_default()
+    }
+
+    /// Returns the native VARA balance of a given user (query)
+    pub fn query_user_native_balance(&self, user: ActorId) -> u128 {
+        ContractState::state_ref().native_balances.get(&user).cloned().unwrap_or_default()
+    }
+
+    /// Returns the native VARA held for all users (query)
+    pub fn query_total_native(&self) -> u128 {
+        ContractState::state_ref().total_native
+    }
+
+    /// Returns the deposits that timed out and still need to be reconciled (query)
+    pub fn query_timed_out_operations(&self) -> Vec<PendingOperation> {
+        let mut operations: Vec<PendingOperation> = ContractState::state_ref()
+            .timed_out_operations
+            .values()
+            .cloned()
+            .collect();
+        operations.sort_unstable_by_key(|operation| operation.op_id);
+        operations
+    }
+
+    /// Returns a page of the exported state, including escrows and sessions (admin only).
+    pub fn export_state(&self, offset: u32, limit: u32) -> StateEnvelope {
+        let state = ContractState::state_ref();
+        assert_eq!(state.admin, msg::source(), "Not admin");
+        snapshot::export_page(
+            state,
+            EscrowState::state_ref(),
+            Storage::get_session_map(),
+            offset,
+            limit,
+        )
+    }
+
+    /// Applies a chunk exported by a previous deployment, until the program is
+    /// sealed or the import window ends (admin only). Entries already imported
+    /// are overwritten, so a chunk can be sent again; the other calls of the
+    /// program are refused until then.
+    pub fn import_state(&mut self, chunk: StateEnvelope) -> Result<Events, MigrationError> {
+        let state = ContractState::state_mut();
+        assert_eq!(state.admin, msg::source(), "Not admin");
+        assert!(exec::block_height() < state.import_open_until, "State is sealed");
+
+        let entries = snapshot::import_chunk(
+            state,
+            EscrowState::state_mut(),
+            Storage::get_session_map_mut(),
+            chunk,
+        )?;
+        let event = Events::StateImported { entries };
+        self.emit_event(event.clone()).expect("Event error");
+        Ok(event)
+    }
+
+    /// Closes the import window for good (admin only).
+    pub fn seal_state(&mut self) -> Events {
+        let state = ContractState::state_mut();
+        assert_eq!(state.admin, msg::source(), "Not admin");
+        state.import_open_until = state.import_open_until.min(exec::block_height());
+        self.emit_event(Events::StateSealed).expect("Event error");
+        Events::StateSealed
+    }
+
+    /// Returns the layout version of the program state (query)
+    pub fn query_state_version(&self) -> u32 {
+        ContractState::version()
+    }
+
     /// Returns the full state (query)
     pub fn query_state(&self) -> IoContractState {
         ContractState::state_ref().clone().into()
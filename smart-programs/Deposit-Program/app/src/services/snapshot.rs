@@ -0,0 +1,200 @@
+// Snapshot of the deposit program: balances, locks, rewards, escrows and
+// sessions, exported page by page and imported by the next deployment.
+
+use sails_rs::prelude::*;
+
+use super::escrow::{Escrow, EscrowState};
+use super::migration::{migrate, MigrationError, StateEnvelope, STATE_VERSION};
+use super::rewards::RewardPool;
+use super::service::{ContractState, LockEntry, PendingOperation};
+use crate::{SessionData, SessionMap};
+
+pub const MAX_EXPORT_PAGE_SIZE: u32 = 200;
+
+// One item of the exported state. Operations still in flight are not exported,
+// and the admin stays the deployer of the new program. Importing only restores
+// the accounting: tokens and VARA held by the old program must be moved separately.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum StateEntry {
+    Totals {
+        total_received: u128,
+        total_native: u128,
+        next_operation_id: u64,
+        next_lock_id: u64,
+        next_escrow_id: u64,
+    },
+    RewardPool(RewardPool),
+    TokenHolding(ActorId, u128),
+    UserBalance(ActorId, u128),
+    TokenBalance { user: ActorId, token: ActorId, amount: u128 },
+    NativeBalance(ActorId, u128),
+    Lock(ActorId, LockEntry),
+    RewardDebt(ActorId, u128),
+    UnclaimedReward(ActorId, u128),
+    TimedOutOperation(PendingOperation),
+    Escrow(Escrow),
+    Session(ActorId, SessionData),
+}
+
+// Every entry of the state in key order, so pages can be fetched one by one
+pub fn state_entries<'a>(
+    state: &'a ContractState,
+    escrows: &'a EscrowState,
+    sessions: &'a SessionMap,
+) -> impl Iterator<Item = StateEntry> + 'a {
+    let totals = StateEntry::Totals {
+        total_received: state.total_received,
+        total_native: state.total_native,
+        next_operation_id: state.next_operation_id,
+        next_lock_id: state.next_lock_id,
+        next_escrow_id: escrows.next_escrow_id,
+    };
+    let reward_pool = state.rewards.clone().map(StateEntry::RewardPool);
+
+    let token_holdings = state
+        .token_holdings
+        .iter()
+        .map(|(token, amount)| StateEntry::TokenHolding(*token, *amount));
+    let user_balances = state
+        .user_balances
+        .iter()
+        .map(|(user, amount)| StateEntry::UserBalance(*user, *amount));
+    let token_balances = state
+        .token_balances
+        .iter()
+        .map(|((user, token), amount)| StateEntry::TokenBalance {
+            user: *user,
+            token: *token,
+            amount: *amount,
+        });
+    let native_balances = state
+        .native_balances
+        .iter()
+        .map(|(user, amount)| StateEntry::NativeBalance(*user, *amount));
+    let locks = state.locks.iter().flat_map(|(user, locks)| {
+        locks
+            .iter()
+            .map(move |lock| StateEntry::Lock(*user, lock.clone()))
+    });
+    let reward_debts = state
+        .reward_debts
+        .iter()
+        .map(|(user, amount)| StateEntry::RewardDebt(*user, *amount));
+    let unclaimed_rewards = state
+        .unclaimed_rewards
+        .iter()
+        .map(|(user, amount)| StateEntry::UnclaimedReward(*user, *amount));
+    let timed_out_operations = state
+        .timed_out_operations
+        .values()
+        .map(|operation| StateEntry::TimedOutOperation(operation.clone()));
+    let escrow_entries = escrows
+        .escrows
+        .values()
+        .map(|escrow| StateEntry::Escrow(escrow.clone()));
+    let session_entries = sessions
+        .iter()
+        .map(|((account, _), session)| StateEntry::Session(*account, session.clone()));
+
+    core::iter::once(totals)
+        .chain(reward_pool)
+        .chain(token_holdings)
+        .chain(user_balances)
+        .chain(token_balances)
+        .chain(native_balances)
+        .chain(locks)
+        .chain(reward_debts)
+        .chain(unclaimed_rewards)
+        .chain(timed_out_operations)
+        .chain(escrow_entries)
+        .chain(session_entries)
+}
+
+pub fn export_page(
+    state: &ContractState,
+    escrows: &EscrowState,
+    sessions: &SessionMap,
+    offset: u32,
+    limit: u32,
+) -> StateEnvelope {
+    let entries: Vec<StateEntry> = state_entries(state, escrows, sessions)
+        .skip(offset as usize)
+        .take(limit.min(MAX_EXPORT_PAGE_SIZE) as usize)
+        .collect();
+    StateEnvelope {
+        version: STATE_VERSION,
+        payload: entries.encode(),
+    }
+}
+
+// Brings the chunk to the current layout and applies it, returning the number of
+// entries. Every entry overwrites what is stored under its key, so importing a
+// chunk twice leaves the same state.
+pub fn import_chunk(
+    state: &mut ContractState,
+    escrows: &mut EscrowState,
+    sessions: &mut SessionMap,
+    chunk: StateEnvelope,
+) -> Result<u32, MigrationError> {
+    let chunk = migrate(chunk)?;
+    let entries = Vec::<StateEntry>::decode(&mut chunk.payload.as_slice())
+        .map_err(|_| MigrationError::InvalidPayload(chunk.version))?;
+
+    let count = entries.len() as u32;
+    for entry in entries {
+        match entry {
+            StateEntry::Totals {
+                total_received,
+                total_native,
+                next_operation_id,
+                next_lock_id,
+                next_escrow_id,
+            } => {
+                state.total_received = total_received;
+                state.total_native = total_native;
+                state.next_operation_id = next_operation_id;
+                state.next_lock_id = next_lock_id;
+                escrows.next_escrow_id = next_escrow_id;
+            }
+            StateEntry::RewardPool(pool) => state.rewards = Some(pool),
+            StateEntry::TokenHolding(token, amount) => {
+                state.token_holdings.insert(token, amount);
+            }
+            StateEntry::UserBalance(user, amount) => {
+                state.user_balances.insert(user, amount);
+            }
+            StateEntry::TokenBalance { user, token, amount } => {
+                state.token_balances.insert((user, token), amount);
+            }
+            StateEntry::NativeBalance(user, amount) => {
+                state.native_balances.insert(user, amount);
+            }
+            StateEntry::Lock(user, lock) => {
+                let locks = state.locks.entry(user).or_default();
+                match locks.iter_mut().find(|existing| existing.lock_id == lock.lock_id) {
+                    Some(existing) => *existing = lock,
+                    None => locks.push(lock),
+                }
+            }
+            StateEntry::RewardDebt(user, amount) => {
+                state.reward_debts.insert(user, amount);
+            }
+            StateEntry::UnclaimedReward(user, amount) => {
+                state.unclaimed_rewards.insert(user, amount);
+            }
+            StateEntry::TimedOutOperation(operation) => {
+                state.timed_out_operations.insert(operation.op_id, operation);
+            }
+            StateEntry::Escrow(escrow) => {
+                escrows.remove_escrow(escrow.escrow_id);
+                escrows.insert_escrow(escrow);
+            }
+            StateEntry::Session(account, session) => {
+                sessions.insert((account, session.key), session);
+            }
+        }
+    }
+    Ok(count)
+}
@@ -0,0 +1,60 @@
+use sails_rs::{
+    prelude::*,
+    gstd::{msg, exec},
+};
+use extended_vft_client::vft::io as vft_io;
+use sails_rs::calls::ActionIo;
+
+use super::service::{DepositError, REPLY_TIMEOUT_BLOCKS};
+
+// Sends a request to a token contract and waits for its reply, up to REPLY_TIMEOUT_BLOCKS
+async fn send_token_request(token_contract: ActorId, request: Vec<u8>) -> Result<Vec<u8>, DepositError> {
+    msg::send_bytes_with_gas_for_reply(token_contract, request, 5_000_000_000, 0, 0)
+        .map_err(|_| DepositError::SendFailed)?
+        .up_to(Some(REPLY_TIMEOUT_BLOCKS))
+        .map_err(|_| DepositError::SendFailed)?
+        .await
+        .map_err(|e| match e {
+            gstd::errors::Error::Timeout(..) => DepositError::ReplyTimeout,
+            _ => DepositError::TransferFailed,
+        })
+}
+
+// Moves `amount` tokens from `from` to this contract and waits for the token reply
+pub(crate) async fn transfer_from_actor(
+    token_contract: ActorId,
+    from: ActorId,
+    amount: u128,
+) -> Result<(), DepositError> {
+    let request = vft_io::TransferFrom::encode_call(from, exec::program_id(), U256::from(amount));
+    let reply = send_token_request(token_contract, request).await?;
+
+    match vft_io::TransferFrom::decode_reply(reply) {
+        Ok(true) => Ok(()),
+        _ => Err(DepositError::TransferFailed),
+    }
+}
+
+// Moves `amount` tokens from this contract to `to` and waits for the token reply
+pub(crate) async fn transfer_to_actor(token_contract: ActorId, to: ActorId, amount: u128) -> Result<(), DepositError> {
+    let request = vft_io::Transfer::encode_call(to, U256::from(amount));
+    let reply = send_token_request(token_contract, request).await?;
+
+    match vft_io::Transfer::decode_reply(reply) {
+        Ok(true) => Ok(()),
+        _ => Err(DepositError::TransferFailed),
+    }
+}
+
+// Asks the token contract for the balance this contract holds
+pub(crate) async fn token_balance_of_program(token_contract: ActorId) -> Result<u128, DepositError> {
+    let request = vft_io::BalanceOf::encode_call(exec::program_id());
+    let reply = send_token_request(token_contract, request)
+        .await
+        .map_err(|_| DepositError::BalanceQueryFailed)?;
+
+    let balance = vft_io::BalanceOf::decode_reply(reply)
+        .map_err(|_| DepositError::BalanceQueryFailed)?;
+    // Balances above u128 can only cover more than any credited amount
+    Ok(if balance > U256::from(u128::MAX) { u128::MAX } else { balance.low_u128() })
+}
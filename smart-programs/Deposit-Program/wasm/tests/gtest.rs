@@ -0,0 +1,875 @@
+use app::{
+    services::escrow::{Escrow, EscrowError, EscrowEvents, EscrowStatus},
+    services::service::{ActionsForSession, DepositError, Events, LockEntry, PendingOperation, REPLY_TIMEOUT_BLOCKS},
+    Config, SessionData, SessionScope, SignatureData, SpendAllowance, SpendLimit,
+};
+use extended_vft_client::vft::io as vft_io;
+use gtest::{constants::EXISTENTIAL_DEPOSIT, BlockRunResult, Log, Program, System};
+use gtest_helpers::{call, deploy, reply_to, PROGRAM_ID};
+use sails_rs::{calls::ActionIo, prelude::*};
+
+const ADMIN: u64 = 10;
+const ALICE: u64 = 11;
+const BOB: u64 = 12;
+const TOKEN: u64 = 20;
+// Session key of Alice's sessions
+const KEY: u64 = 30;
+
+// Deploys the program with its state import window still open
+fn deploy_program(system: &System) -> Program<'_> {
+    let config = Config {
+        gas_to_delete_session: 10_000_000_000,
+        minimum_session_duration_ms: 180_000,
+        ms_per_block: 3_000,
+    };
+    deploy(system, wasm::WASM_BINARY, ADMIN, &[ADMIN, ALICE, BOB, TOKEN, KEY], config)
+}
+
+// Deploys the program and seals it, as a deployment without state to import does
+fn init_program(system: &System) -> Program<'_> {
+    let program = deploy_program(system);
+    let id = program.send_bytes(ADMIN, service_call("SealState", ()));
+    assert!(system.run_next_block().succeed.contains(&id));
+    program
+}
+
+fn service_call(method: &str, args: impl Encode) -> Vec<u8> {
+    call("Service", method, args)
+}
+
+fn deposit(program: &Program, from: u64, amount: u128) {
+    program.send_bytes(
+        from,
+        service_call("Deposit", (ActorId::from(TOKEN), amount, None::<ActorId>)),
+    );
+}
+
+fn pending_operations(system: &System, program: &Program) -> Vec<PendingOperation> {
+    program.send_bytes(ADMIN, service_call("QueryPendingOperations", ()));
+    let result = system.run_next_block();
+    reply_to(&result, ADMIN).expect("No reply to pending operations query")
+}
+
+// Answers the TransferFrom that the program sent to the token on behalf of `from`
+fn token_reply(system: &System, from: u64, amount: u128, success: bool) -> BlockRunResult {
+    let request =
+        vft_io::TransferFrom::encode_call(ActorId::from(from), ActorId::from(PROGRAM_ID), U256::from(amount));
+    let log = Log::builder()
+        .source(PROGRAM_ID)
+        .dest(TOKEN)
+        .payload_bytes(request);
+
+    let mailbox = system.get_mailbox(TOKEN);
+    assert!(mailbox.contains(&log), "TransferFrom was not sent to the token");
+    mailbox
+        .reply_bytes(log, [vft_io::TransferFrom::ROUTE.to_vec(), success.encode()].concat(), 0)
+        .expect("Failed to reply from the token");
+    system.run_next_block()
+}
+
+// Answers the Transfer that the program sent to the token in favour of `to`
+fn transfer_reply(system: &System, to: u64, amount: u128, success: bool) -> BlockRunResult {
+    let request = vft_io::Transfer::encode_call(ActorId::from(to), U256::from(amount));
+    let log = Log::builder()
+        .source(PROGRAM_ID)
+        .dest(TOKEN)
+        .payload_bytes(request);
+
+    let mailbox = system.get_mailbox(TOKEN);
+    assert!(mailbox.contains(&log), "Transfer was not sent to the token");
+    mailbox
+        .reply_bytes(log, [vft_io::Transfer::ROUTE.to_vec(), success.encode()].concat(), 0)
+        .expect("Failed to reply from the token");
+    system.run_next_block()
+}
+
+#[test]
+fn operations_wait_for_the_state_to_be_sealed() {
+    let system = System::new();
+    let program = deploy_program(&system);
+
+    let deposit_native = service_call("DepositNative", None::<ActorId>);
+    let id = program.send_bytes_with_value(ALICE, deposit_native.clone(), EXISTENTIAL_DEPOSIT);
+    let result = system.run_next_block();
+    assert!(panicked_with(&result, ALICE, "State import is still open"));
+    assert!(result.failed.contains(&id));
+
+    program.send_bytes(ADMIN, service_call("SealState", ()));
+    system.run_next_block();
+    let id = program.send_bytes_with_value(ALICE, deposit_native, EXISTENTIAL_DEPOSIT);
+    assert!(system.run_next_block().succeed.contains(&id));
+}
+
+#[test]
+fn overlapping_deposits_from_same_actor_are_rejected() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    deposit(&program, ALICE, 100);
+    let result = system.run_next_block();
+    assert!(reply_to::<Result<Events, DepositError>>(&result, ALICE).is_none());
+
+    deposit(&program, ALICE, 50);
+    let result = system.run_next_block();
+    assert_eq!(
+        reply_to::<Result<Events, DepositError>>(&result, ALICE),
+        Some(Err(DepositError::OperationInProgress))
+    );
+
+    let pending = pending_operations(&system, &program);
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].actor, ActorId::from(ALICE));
+    assert_eq!(pending[0].amount, 100);
+}
+
+#[test]
+fn different_actors_can_deposit_concurrently() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    deposit(&program, ALICE, 100);
+    deposit(&program, BOB, 30);
+    system.run_next_block();
+    assert_eq!(pending_operations(&system, &program).len(), 2);
+
+    let result = token_reply(&system, ALICE, 100, true);
+    assert_eq!(
+        reply_to::<Result<Events, DepositError>>(&result, ALICE),
+        Some(Ok(Events::Deposited {
+            actor: ActorId::from(ALICE),
+            token: ActorId::from(TOKEN),
+            amount: 100,
+            new_balance: 100,
+            via_session: false,
+        }))
+    );
+
+    let pending = pending_operations(&system, &program);
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].actor, ActorId::from(BOB));
+
+    // Alice's lock is released once her deposit has been settled
+    deposit(&program, ALICE, 20);
+    system.run_next_block();
+    assert_eq!(pending_operations(&system, &program).len(), 2);
+}
+
+#[test]
+fn failed_transfer_releases_the_lock() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    deposit(&program, ALICE, 100);
+    system.run_next_block();
+
+    let result = token_reply(&system, ALICE, 100, false);
+    assert_eq!(
+        reply_to::<Result<Events, DepositError>>(&result, ALICE),
+        Some(Err(DepositError::TransferFailed))
+    );
+    assert!(pending_operations(&system, &program).is_empty());
+
+    program.send_bytes(ADMIN, service_call("QueryUserBalance", ActorId::from(ALICE)));
+    let result = system.run_next_block();
+    assert_eq!(reply_to::<u128>(&result, ADMIN), Some(0));
+}
+
+// Session of Alice held by KEY, without limits
+fn signature_data(allowed_actions: Vec<ActionsForSession>) -> SignatureData {
+    SignatureData {
+        key: ActorId::from(KEY),
+        duration: 180_000,
+        allowed_actions,
+        spend_limits: vec![],
+        max_calls: None,
+        scope: SessionScope::default(),
+        allow_renewal: false,
+    }
+}
+
+fn create_session(system: &System, program: &Program, signature_data: SignatureData) {
+    let id = program.send_bytes(
+        ALICE,
+        call("Session", "CreateSession", (signature_data, None::<Vec<u8>>)),
+    );
+    assert!(system.run_next_block().succeed.contains(&id));
+}
+
+fn session(system: &System, program: &Program) -> Option<SessionData> {
+    program.send_bytes(
+        ADMIN,
+        call("Session", "SessionForTheKey", (ActorId::from(ALICE), ActorId::from(KEY))),
+    );
+    let result = system.run_next_block();
+    reply_to(&result, ADMIN).expect("No reply to session query")
+}
+
+#[test]
+fn pending_session_call_holds_its_call() {
+    let system = System::new();
+    let program = init_program(&system);
+    create_session(
+        &system,
+        &program,
+        SignatureData {
+            max_calls: Some(1),
+            ..signature_data(vec![ActionsForSession::Deposit, ActionsForSession::DepositNative])
+        },
+    );
+
+    program.send_bytes(
+        KEY,
+        service_call("Deposit", (ActorId::from(TOKEN), 100u128, Some(ActorId::from(ALICE)))),
+    );
+    system.run_next_block();
+    assert_eq!(session(&system, &program).unwrap().max_calls, Some(0));
+
+    // The only call is taken by the deposit waiting for the token
+    let native_call = service_call("DepositNative", Some(ActorId::from(ALICE)));
+    let id = program.send_bytes_with_value(KEY, native_call.clone(), EXISTENTIAL_DEPOSIT);
+    assert!(system.run_next_block().failed.contains(&id));
+
+    // A failed deposit gives the call back
+    let result = token_reply(&system, ALICE, 100, false);
+    assert_eq!(
+        reply_to::<Result<Events, DepositError>>(&result, KEY),
+        Some(Err(DepositError::TransferFailed))
+    );
+    assert_eq!(session(&system, &program).unwrap().max_calls, Some(1));
+
+    let id = program.send_bytes_with_value(KEY, native_call, EXISTENTIAL_DEPOSIT);
+    assert!(system.run_next_block().succeed.contains(&id));
+    assert_eq!(session(&system, &program).unwrap().max_calls, Some(0));
+}
+
+// Runs blocks until the reply to `actor` arrives, at most until the token reply times out
+fn wait_for_timeout(system: &System, actor: u64) {
+    let mut reply = None;
+    for _ in 0..=REPLY_TIMEOUT_BLOCKS {
+        let result = system.run_next_block();
+        reply = reply_to::<Result<Events, DepositError>>(&result, actor);
+        if reply.is_some() {
+            break;
+        }
+    }
+    assert_eq!(reply, Some(Err(DepositError::ReplyTimeout)));
+}
+
+fn timed_out_operations(system: &System, program: &Program) -> Vec<PendingOperation> {
+    program.send_bytes(ADMIN, service_call("QueryTimedOutOperations", ()));
+    let result = system.run_next_block();
+    reply_to(&result, ADMIN).expect("No reply to timed out operations query")
+}
+
+// Answers the BalanceOf the program sent to the token with `balance`
+fn balance_reply(system: &System, balance: u128) -> BlockRunResult {
+    let log = Log::builder()
+        .source(PROGRAM_ID)
+        .dest(TOKEN)
+        .payload_bytes(vft_io::BalanceOf::encode_call(ActorId::from(PROGRAM_ID)));
+    system
+        .get_mailbox(TOKEN)
+        .reply_bytes(log, [vft_io::BalanceOf::ROUTE.to_vec(), U256::from(balance).encode()].concat(), 0)
+        .expect("Failed to reply from the token");
+    system.run_next_block()
+}
+
+fn user_balance(system: &System, program: &Program, user: u64) -> u128 {
+    program.send_bytes(ADMIN, service_call("QueryUserBalance", ActorId::from(user)));
+    let result = system.run_next_block();
+    reply_to(&result, ADMIN).expect("No reply to balance query")
+}
+
+#[test]
+fn timed_out_deposit_can_be_reconciled() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    deposit(&program, ALICE, 100);
+    wait_for_timeout(&system, ALICE);
+    assert!(pending_operations(&system, &program).is_empty());
+
+    let timed_out = timed_out_operations(&system, &program);
+    assert_eq!(timed_out.len(), 1);
+    let op_id = timed_out[0].op_id;
+
+    // Only the admin may reconcile
+    let not_admin = program.send_bytes(ALICE, service_call("Reconcile", op_id));
+    assert!(system.run_next_block().failed.contains(&not_admin));
+
+    program.send_bytes(ADMIN, service_call("Reconcile", op_id));
+    system.run_next_block();
+
+    // The token reports that the transfer went through
+    let result = balance_reply(&system, 100);
+    assert_eq!(
+        reply_to::<Result<Events, DepositError>>(&result, ADMIN),
+        Some(Ok(Events::OperationReconciled {
+            op_id,
+            actor: ActorId::from(ALICE),
+            token: ActorId::from(TOKEN),
+            amount: 100,
+            settled: true,
+        }))
+    );
+
+    assert_eq!(user_balance(&system, &program, ALICE), 100);
+}
+
+#[test]
+fn operation_being_reconciled_is_not_settled_twice() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    deposit(&program, ALICE, 100);
+    wait_for_timeout(&system, ALICE);
+    let op_id = timed_out_operations(&system, &program)[0].op_id;
+
+    program.send_bytes(ADMIN, service_call("Reconcile", op_id));
+    system.run_next_block();
+
+    // The balance query is in flight: the operation stays timed out but can't be resolved again
+    assert_eq!(timed_out_operations(&system, &program).len(), 1);
+    for (method, args) in [("Reconcile", op_id.encode()), ("SettleOperation", (op_id, true).encode())] {
+        program.send_bytes(ADMIN, [service_call(method, ()), args].concat());
+        let result = system.run_next_block();
+        assert_eq!(
+            reply_to::<Result<Events, DepositError>>(&result, ADMIN),
+            Some(Err(DepositError::OperationInProgress))
+        );
+    }
+
+    // Bob's deposit on the same token could already be in the balance the token reports
+    deposit(&program, BOB, 30);
+    system.run_next_block();
+    let result = balance_reply(&system, 130);
+    assert_eq!(
+        reply_to::<Result<Events, DepositError>>(&result, ADMIN),
+        Some(Err(DepositError::OperationInProgress))
+    );
+    assert_eq!(timed_out_operations(&system, &program).len(), 1);
+
+    token_reply(&system, BOB, 30, true);
+    program.send_bytes(ADMIN, service_call("Reconcile", op_id));
+    system.run_next_block();
+    let result = balance_reply(&system, 130);
+    assert!(matches!(
+        reply_to::<Result<Events, DepositError>>(&result, ADMIN),
+        Some(Ok(Events::OperationReconciled { settled: true, .. }))
+    ));
+    assert!(timed_out_operations(&system, &program).is_empty());
+    assert_eq!(user_balance(&system, &program, ALICE), 100);
+}
+
+#[test]
+fn timed_out_deposits_on_same_token_are_not_reconciled_from_the_balance() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    deposit(&program, ALICE, 100);
+    deposit(&program, BOB, 30);
+    let mut replies = 0;
+    for _ in 0..=REPLY_TIMEOUT_BLOCKS + 1 {
+        let result = system.run_next_block();
+        for actor in [ALICE, BOB] {
+            if let Some(reply) = reply_to::<Result<Events, DepositError>>(&result, actor) {
+                assert_eq!(reply, Err(DepositError::ReplyTimeout));
+                replies += 1;
+            }
+        }
+        if replies == 2 {
+            break;
+        }
+    }
+    assert_eq!(replies, 2);
+
+    let timed_out = timed_out_operations(&system, &program);
+    assert_eq!(timed_out.len(), 2);
+    let alice_op = timed_out.iter().find(|op| op.actor == ActorId::from(ALICE)).unwrap().op_id;
+    let bob_op = timed_out.iter().find(|op| op.actor == ActorId::from(BOB)).unwrap().op_id;
+
+    // Bob's tokens arrived but Alice's didn't: the balance alone would credit Alice
+    program.send_bytes(ADMIN, service_call("Reconcile", alice_op));
+    let result = system.run_next_block();
+    assert_eq!(
+        reply_to::<Result<Events, DepositError>>(&result, ADMIN),
+        Some(Err(DepositError::AmbiguousReconciliation))
+    );
+    assert_eq!(timed_out_operations(&system, &program).len(), 2);
+
+    program.send_bytes(ADMIN, service_call("SettleOperation", (alice_op, false)));
+    let result = system.run_next_block();
+    assert!(matches!(
+        reply_to::<Result<Events, DepositError>>(&result, ADMIN),
+        Some(Ok(Events::OperationReconciled { settled: false, .. }))
+    ));
+
+    // With Alice's operation resolved, Bob's can be checked against the balance
+    program.send_bytes(ADMIN, service_call("Reconcile", bob_op));
+    system.run_next_block();
+    let result = balance_reply(&system, 30);
+    assert!(matches!(
+        reply_to::<Result<Events, DepositError>>(&result, ADMIN),
+        Some(Ok(Events::OperationReconciled { settled: true, .. }))
+    ));
+
+    assert_eq!(user_balance(&system, &program, ALICE), 0);
+    assert_eq!(user_balance(&system, &program, BOB), 30);
+}
+
+fn withdraw_native(system: &System, program: &Program, from: u64, amount: u128) -> Option<Result<Events, DepositError>> {
+    program.send_bytes(from, service_call("WithdrawNative", (amount, None::<ActorId>)));
+    let result = system.run_next_block();
+    reply_to(&result, from)
+}
+
+#[test]
+fn native_deposit_can_be_withdrawn() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    let initial = program.balance();
+    let amount = 5 * EXISTENTIAL_DEPOSIT;
+    program.send_bytes_with_value(ALICE, service_call("DepositNative", None::<ActorId>), amount);
+    let result = system.run_next_block();
+    assert_eq!(
+        reply_to::<Events>(&result, ALICE),
+        Some(Events::NativeDeposited {
+            actor: ActorId::from(ALICE),
+            amount,
+            new_balance: amount,
+            via_session: false,
+        })
+    );
+    assert_eq!(program.balance(), initial + amount);
+
+    assert_eq!(
+        withdraw_native(&system, &program, ALICE, 2 * EXISTENTIAL_DEPOSIT),
+        Some(Ok(Events::NativeWithdrawn {
+            actor: ActorId::from(ALICE),
+            amount: 2 * EXISTENTIAL_DEPOSIT,
+            new_balance: 3 * EXISTENTIAL_DEPOSIT,
+            via_session: false,
+        }))
+    );
+    assert_eq!(program.balance(), initial + 3 * EXISTENTIAL_DEPOSIT);
+
+    program.send_bytes(ADMIN, service_call("QueryUserNativeBalance", ActorId::from(ALICE)));
+    let result = system.run_next_block();
+    assert_eq!(reply_to::<u128>(&result, ADMIN), Some(3 * EXISTENTIAL_DEPOSIT));
+}
+
+#[test]
+fn native_withdrawals_below_existential_deposit_are_rejected() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    let initial = program.balance();
+    let amount = 2 * EXISTENTIAL_DEPOSIT;
+    program.send_bytes_with_value(ALICE, service_call("DepositNative", None::<ActorId>), amount);
+    system.run_next_block();
+
+    // Dust can't be sent
+    assert_eq!(
+        withdraw_native(&system, &program, ALICE, EXISTENTIAL_DEPOSIT / 2),
+        Some(Err(DepositError::BelowExistentialDeposit))
+    );
+    // Nor left behind, where it could never be withdrawn
+    assert_eq!(
+        withdraw_native(&system, &program, ALICE, amount - EXISTENTIAL_DEPOSIT / 2),
+        Some(Err(DepositError::BelowExistentialDeposit))
+    );
+    assert_eq!(program.balance(), initial + amount);
+
+    // The whole balance always goes through
+    assert!(matches!(
+        withdraw_native(&system, &program, ALICE, amount),
+        Some(Ok(Events::NativeWithdrawn { new_balance: 0, .. }))
+    ));
+    assert_eq!(program.balance(), initial);
+}
+
+fn user_locks(system: &System, program: &Program, user: u64) -> Vec<LockEntry> {
+    program.send_bytes(ADMIN, service_call("QueryUserLocks", ActorId::from(user)));
+    let result = system.run_next_block();
+    reply_to(&result, ADMIN).expect("No reply to locks query")
+}
+
+fn claim_unlocked(program: &Program, from: u64) {
+    program.send_bytes(
+        from,
+        service_call("ClaimUnlocked", (ActorId::from(TOKEN), None::<ActorId>)),
+    );
+}
+
+#[test]
+fn locked_deposit_is_claimable_only_after_unlock() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    let unlock_at = system.block_timestamp() + 10 * 3_000;
+    program.send_bytes(
+        ALICE,
+        service_call("DepositLocked", (ActorId::from(TOKEN), 100_u128, unlock_at, None::<ActorId>)),
+    );
+    system.run_next_block();
+    let result = token_reply(&system, ALICE, 100, true);
+    assert!(matches!(
+        reply_to::<Result<Events, DepositError>>(&result, ALICE),
+        Some(Ok(Events::Locked { amount: 100, .. }))
+    ));
+    let locks = user_locks(&system, &program, ALICE);
+    assert_eq!(locks.len(), 1);
+    assert_eq!(locks[0].unlock_at, unlock_at);
+
+    claim_unlocked(&program, ALICE);
+    let result = system.run_next_block();
+    assert_eq!(
+        reply_to::<Result<Events, DepositError>>(&result, ALICE),
+        Some(Err(DepositError::NothingToClaim))
+    );
+
+    while system.block_timestamp() < unlock_at {
+        system.run_next_block();
+    }
+    claim_unlocked(&program, ALICE);
+    system.run_next_block();
+    let result = transfer_reply(&system, ALICE, 100, true);
+    assert_eq!(
+        reply_to::<Result<Events, DepositError>>(&result, ALICE),
+        Some(Ok(Events::UnlockedClaimed {
+            actor: ActorId::from(ALICE),
+            token: ActorId::from(TOKEN),
+            amount: 100,
+            via_session: false,
+        }))
+    );
+    assert!(user_locks(&system, &program, ALICE).is_empty());
+}
+
+#[test]
+fn failed_claim_restores_the_locks() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    let unlock_at = system.block_timestamp() + 3 * 3_000;
+    program.send_bytes(
+        ALICE,
+        service_call("DepositLocked", (ActorId::from(TOKEN), 100_u128, unlock_at, None::<ActorId>)),
+    );
+    system.run_next_block();
+    token_reply(&system, ALICE, 100, true);
+    while system.block_timestamp() < unlock_at {
+        system.run_next_block();
+    }
+
+    claim_unlocked(&program, ALICE);
+    system.run_next_block();
+    let result = transfer_reply(&system, ALICE, 100, false);
+    assert_eq!(
+        reply_to::<Result<Events, DepositError>>(&result, ALICE),
+        Some(Err(DepositError::TransferFailed))
+    );
+    assert_eq!(user_locks(&system, &program, ALICE).len(), 1);
+}
+
+fn internal_transfer(program: &Program, from: u64, to: u64, amount: u128) -> MessageId {
+    program.send_bytes(
+        from,
+        service_call("InternalTransfer", (ActorId::from(to), ActorId::from(TOKEN), amount, None::<ActorId>)),
+    )
+}
+
+#[test]
+fn internal_transfer_moves_deposited_balance() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    deposit(&program, ALICE, 100);
+    system.run_next_block();
+    token_reply(&system, ALICE, 100, true);
+
+    internal_transfer(&program, ALICE, BOB, 40);
+    let result = system.run_next_block();
+    assert_eq!(
+        reply_to::<Events>(&result, ALICE),
+        Some(Events::InternalTransferred {
+            from: ActorId::from(ALICE),
+            to: ActorId::from(BOB),
+            token: ActorId::from(TOKEN),
+            amount: 40,
+            from_balance: 60,
+            to_balance: 40,
+            via_session: false,
+        })
+    );
+    assert_eq!(user_balance(&system, &program, ALICE), 60);
+    assert_eq!(user_balance(&system, &program, BOB), 40);
+
+    // Overdrafts and transfers to oneself are rejected
+    let id = internal_transfer(&program, BOB, ALICE, 41);
+    assert!(system.run_next_block().failed.contains(&id));
+    let id = internal_transfer(&program, ALICE, ALICE, 10);
+    assert!(system.run_next_block().failed.contains(&id));
+    assert_eq!(user_balance(&system, &program, ALICE), 60);
+    assert_eq!(user_balance(&system, &program, BOB), 40);
+}
+
+// Alice's internal transfer to Bob through her session
+fn session_transfer(system: &System, program: &Program, amount: u128) -> BlockRunResult {
+    program.send_bytes(
+        KEY,
+        service_call(
+            "InternalTransfer",
+            (ActorId::from(BOB), ActorId::from(TOKEN), amount, Some(ActorId::from(ALICE))),
+        ),
+    );
+    system.run_next_block()
+}
+
+// Whether the call of `actor` failed with `message`
+fn panicked_with(result: &BlockRunResult, actor: u64, message: &str) -> bool {
+    result.log().iter().any(|log| {
+        log.destination() == ActorId::from(actor)
+            && log.reply_code().is_some_and(|code| code.is_error())
+            && log.payload().windows(message.len()).any(|window| window == message.as_bytes())
+    })
+}
+
+fn remaining_allowance(system: &System, program: &Program) -> Vec<SpendAllowance> {
+    program.send_bytes(
+        ADMIN,
+        call("Session", "RemainingAllowance", (ActorId::from(ALICE), ActorId::from(KEY))),
+    );
+    let result = system.run_next_block();
+    reply_to(&result, ADMIN).expect("No reply to allowance query")
+}
+
+fn allowance(remaining: u128) -> Vec<SpendAllowance> {
+    vec![SpendAllowance {
+        token: ActorId::from(TOKEN),
+        remaining,
+        per_call: 30,
+    }]
+}
+
+#[test]
+fn session_spend_limits_cap_each_call_and_the_total() {
+    let system = System::new();
+    let program = init_program(&system);
+    deposit(&program, ALICE, 100);
+    system.run_next_block();
+    token_reply(&system, ALICE, 100, true);
+
+    create_session(
+        &system,
+        &program,
+        SignatureData {
+            spend_limits: vec![SpendLimit {
+                token: ActorId::from(TOKEN),
+                total: 50,
+                per_call: 30,
+            }],
+            ..signature_data(vec![ActionsForSession::InternalTransfer, ActionsForSession::DepositNative])
+        },
+    );
+    assert_eq!(remaining_allowance(&system, &program), allowance(50));
+
+    let result = session_transfer(&system, &program, 31);
+    assert!(panicked_with(&result, KEY, "Session per-call limit exceeded"));
+
+    let result = session_transfer(&system, &program, 30);
+    assert!(matches!(reply_to::<Events>(&result, KEY), Some(Events::InternalTransferred { amount: 30, .. })));
+    assert_eq!(remaining_allowance(&system, &program), allowance(20));
+
+    let result = session_transfer(&system, &program, 21);
+    assert!(panicked_with(&result, KEY, "Session spend limit exceeded"));
+    let result = session_transfer(&system, &program, 20);
+    assert!(matches!(reply_to::<Events>(&result, KEY), Some(Events::InternalTransferred { amount: 20, .. })));
+    assert_eq!(remaining_allowance(&system, &program), allowance(0));
+
+    // Native VARA has no limit in this session, so the session can't move it
+    program.send_bytes_with_value(KEY, service_call("DepositNative", Some(ActorId::from(ALICE))), EXISTENTIAL_DEPOSIT);
+    let result = system.run_next_block();
+    assert!(panicked_with(&result, KEY, "Token not allowed for session"));
+
+    assert_eq!(user_balance(&system, &program, ALICE), 50);
+    assert_eq!(user_balance(&system, &program, BOB), 50);
+}
+
+#[test]
+fn failed_session_deposit_refunds_the_spend_limit() {
+    let system = System::new();
+    let program = init_program(&system);
+    create_session(
+        &system,
+        &program,
+        SignatureData {
+            spend_limits: vec![SpendLimit {
+                token: ActorId::from(TOKEN),
+                total: 100,
+                per_call: 30,
+            }],
+            ..signature_data(vec![ActionsForSession::Deposit])
+        },
+    );
+
+    program.send_bytes(
+        KEY,
+        service_call("Deposit", (ActorId::from(TOKEN), 30u128, Some(ActorId::from(ALICE)))),
+    );
+    system.run_next_block();
+    assert_eq!(remaining_allowance(&system, &program), allowance(70));
+
+    let result = token_reply(&system, ALICE, 30, false);
+    assert_eq!(
+        reply_to::<Result<Events, DepositError>>(&result, KEY),
+        Some(Err(DepositError::TransferFailed))
+    );
+    assert_eq!(remaining_allowance(&system, &program), allowance(100));
+}
+
+fn escrow_call(method: &str, args: impl Encode) -> Vec<u8> {
+    call("Escrow", method, args)
+}
+
+// Alice escrows 100 tokens for Bob with the admin as arbiter and returns its id
+fn create_escrow(system: &System, program: &Program, deadline: u64) -> u64 {
+    program.send_bytes(
+        ALICE,
+        escrow_call(
+            "CreateEscrow",
+            (
+                ActorId::from(BOB),
+                ActorId::from(ADMIN),
+                ActorId::from(TOKEN),
+                100_u128,
+                deadline,
+                None::<ActorId>,
+            ),
+        ),
+    );
+    system.run_next_block();
+    let result = token_reply(system, ALICE, 100, true);
+    match reply_to::<Result<EscrowEvents, EscrowError>>(&result, ALICE) {
+        Some(Ok(EscrowEvents::Created { escrow_id, .. })) => escrow_id,
+        reply => std::panic!("Unexpected reply to CreateEscrow: {reply:?}"),
+    }
+}
+
+fn escrow_action(
+    system: &System,
+    program: &Program,
+    from: u64,
+    method: &str,
+    escrow_id: u64,
+) -> Option<Result<EscrowEvents, EscrowError>> {
+    program.send_bytes(from, escrow_call(method, (escrow_id, None::<ActorId>)));
+    let result = system.run_next_block();
+    reply_to(&result, from)
+}
+
+fn escrow_status(system: &System, program: &Program, escrow_id: u64) -> EscrowStatus {
+    program.send_bytes(ADMIN, escrow_call("QueryEscrow", escrow_id));
+    let result = system.run_next_block();
+    let escrow: Option<Escrow> = reply_to(&result, ADMIN).expect("No reply to escrow query");
+    escrow.expect("Unknown escrow").status
+}
+
+#[test]
+fn escrow_is_released_to_the_payee() {
+    let system = System::new();
+    let program = init_program(&system);
+    let escrow_id = create_escrow(&system, &program, system.block_timestamp() + 30_000);
+    assert_eq!(escrow_status(&system, &program, escrow_id), EscrowStatus::Active);
+
+    // The payee can't release the funds to itself
+    assert_eq!(
+        escrow_action(&system, &program, BOB, "ReleaseEscrow", escrow_id),
+        Some(Err(EscrowError::NotAllowed))
+    );
+
+    escrow_action(&system, &program, ALICE, "ReleaseEscrow", escrow_id);
+    let result = transfer_reply(&system, BOB, 100, true);
+    assert_eq!(
+        reply_to::<Result<EscrowEvents, EscrowError>>(&result, ALICE),
+        Some(Ok(EscrowEvents::Released {
+            escrow_id,
+            by: ActorId::from(ALICE),
+            payee: ActorId::from(BOB),
+            amount: 100,
+        }))
+    );
+    assert_eq!(escrow_status(&system, &program, escrow_id), EscrowStatus::Released);
+
+    assert_eq!(
+        escrow_action(&system, &program, ADMIN, "RefundEscrow", escrow_id),
+        Some(Err(EscrowError::NotActive))
+    );
+}
+
+#[test]
+fn escrow_refunds_follow_the_deadline() {
+    let system = System::new();
+    let program = init_program(&system);
+    let deadline = system.block_timestamp() + 30_000;
+    let escrow_id = create_escrow(&system, &program, deadline);
+
+    assert_eq!(
+        escrow_action(&system, &program, ALICE, "RefundEscrow", escrow_id),
+        Some(Err(EscrowError::DeadlineNotReached))
+    );
+    assert_eq!(
+        escrow_action(&system, &program, BOB, "RefundEscrow", escrow_id),
+        Some(Err(EscrowError::NotAllowed))
+    );
+
+    while system.block_timestamp() < deadline {
+        system.run_next_block();
+    }
+    escrow_action(&system, &program, ALICE, "RefundEscrow", escrow_id);
+    let result = transfer_reply(&system, ALICE, 100, true);
+    assert_eq!(
+        reply_to::<Result<EscrowEvents, EscrowError>>(&result, ALICE),
+        Some(Ok(EscrowEvents::Refunded {
+            escrow_id,
+            by: ActorId::from(ALICE),
+            payer: ActorId::from(ALICE),
+            amount: 100,
+        }))
+    );
+    assert_eq!(escrow_status(&system, &program, escrow_id), EscrowStatus::Refunded);
+}
+
+#[test]
+fn arbiter_can_refund_before_the_deadline() {
+    let system = System::new();
+    let program = init_program(&system);
+    let escrow_id = create_escrow(&system, &program, system.block_timestamp() + 30_000);
+
+    escrow_action(&system, &program, ADMIN, "RefundEscrow", escrow_id);
+    let result = transfer_reply(&system, ALICE, 100, true);
+    assert!(matches!(
+        reply_to::<Result<EscrowEvents, EscrowError>>(&result, ADMIN),
+        Some(Ok(EscrowEvents::Refunded { .. }))
+    ));
+}
+
+#[test]
+fn escrows_of_a_user_are_paged_by_id() {
+    let system = System::new();
+    let program = init_program(&system);
+    let deadline = system.block_timestamp() + 60_000;
+    let ids: Vec<u64> = (0..3).map(|_| create_escrow(&system, &program, deadline)).collect();
+
+    let page = |user: u64, offset: u32, limit: u32| -> Vec<u64> {
+        program.send_bytes(ADMIN, escrow_call("QueryEscrowsOf", (ActorId::from(user), offset, limit)));
+        let result = system.run_next_block();
+        let escrows: Vec<Escrow> = reply_to(&result, ADMIN).expect("No reply to escrows query");
+        escrows.into_iter().map(|escrow| escrow.escrow_id).collect()
+    };
+    assert_eq!(page(BOB, 0, 2), ids[..2].to_vec());
+    assert_eq!(page(BOB, 2, 2), ids[2..].to_vec());
+    assert_eq!(page(ADMIN, 1, 10), ids[1..].to_vec());
+    assert!(page(TOKEN, 0, 10).is_empty());
+}
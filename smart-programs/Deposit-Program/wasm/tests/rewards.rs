@@ -0,0 +1,67 @@
+use app::services::rewards::{RewardPool, REWARD_PRECISION};
+use sails_rs::prelude::*;
+
+fn pool(reward_per_block: u128, available: u128, total_staked: u128) -> RewardPool {
+    let mut pool = RewardPool::new(ActorId::from(1), ActorId::from(2), reward_per_block, total_staked, 10);
+    pool.available = available;
+    pool
+}
+
+#[test]
+fn accrue_rounds_shares_down() {
+    let mut pool = pool(10, 100, 3);
+    pool.accrue(11);
+
+    assert_eq!(pool.acc_reward_per_share, 10 * REWARD_PRECISION / 3);
+    assert_eq!(pool.available, 90);
+    // Each third of the stake gets 3, the remainder stays in the contract
+    assert_eq!(pool.earned(1, 0), 3);
+    assert!(3 * pool.earned(1, 0) <= 10);
+    assert_eq!(pool.earned(3, 0), 9);
+}
+
+#[test]
+fn accrue_is_capped_by_available_rewards() {
+    let mut pool = pool(10, 25, 5);
+    pool.accrue(15);
+
+    assert_eq!(pool.available, 0);
+    assert_eq!(pool.earned(5, 0), 25);
+}
+
+#[test]
+fn accrue_without_stake_keeps_the_rewards() {
+    let mut pool = pool(10, 100, 0);
+    pool.accrue(20);
+
+    assert_eq!(pool.last_reward_block, 20);
+    assert_eq!(pool.available, 100);
+    assert_eq!(pool.acc_reward_per_share, 0);
+
+    // Blocks without stake are not paid out later
+    pool.total_staked = 10;
+    pool.accrue(21);
+    assert_eq!(pool.available, 90);
+    assert_eq!(pool.earned(10, 0), 10);
+}
+
+#[test]
+fn accrue_ignores_past_blocks() {
+    let mut pool = pool(10, 100, 1);
+    pool.accrue(10);
+    pool.accrue(5);
+
+    assert_eq!(pool.last_reward_block, 10);
+    assert_eq!(pool.available, 100);
+}
+
+#[test]
+fn accrue_saturates_instead_of_overflowing() {
+    let mut pool = pool(u128::MAX, u128::MAX, 1);
+    pool.accrue(u32::MAX);
+
+    assert_eq!(pool.available, 0);
+    assert_eq!(pool.acc_reward_per_share, u128::MAX);
+    assert_eq!(pool.reward_debt_for(u128::MAX), u128::MAX);
+    assert_eq!(pool.earned(u128::MAX, u128::MAX), 0);
+}
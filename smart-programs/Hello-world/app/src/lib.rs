@@ -1,19 +1,21 @@
 #![no_std]
 
 use sails_rs::prelude::*;
-use session_service::*; 
 
-mod services;
-use services::service::{Service, ActionsForSession}; 
+pub mod services;
+use services::service::{Service, ActionsForSession, ContentLimits}; 
 
-session_service::generate_session_system!(ActionsForSession);  
+session_service::generate_session_system!(
+    ActionsForSession,
+    on_session_removed = services::service::forget_session
+);
 
 pub struct Program;
 
 #[program]
 impl Program {
-    pub fn new(config: Config) -> Self {
-        Service::seed();
+    pub fn new(config: Config, limits: Option<ContentLimits>) -> Self {
+        Service::seed(limits);
         SessionService::init(config);
         Self
     }
@@ -4,50 +4,296 @@ use sails_rs::{
     prelude::*,
     gstd::{msg, exec},
 };
-use sails_rs::collections::HashMap;
+use sails_rs::collections::{BTreeMap, BTreeSet, HashMap};
 
-use crate::{SessionData, Storage}; 
+use crate::{get_actor, record_session_call, refund_session_call, CallArgs, SessionData, Storage};
+use super::migration::{MigrationError, StateEnvelope, VersionedState, IMPORT_WINDOW_BLOCKS, STATE_VERSION};
+use super::snapshot;
 
-pub static mut HELLO_STATE: Option<HelloState> = None;
+pub static mut HELLO_STATE: Option<VersionedState<HelloState>> = None;
+
+// Oldest entries are dropped once a history reaches its limit
+pub const MAX_GREETING_HISTORY: usize = 100;
+pub const MAX_USER_HISTORY: usize = 20;
+pub const MAX_PAGE_SIZE: u32 = 50;
+// Number of greeters kept on the leaderboard
+pub const LEADERBOARD_SIZE: usize = 50;
 
 #[derive(Clone, Default)]
 pub struct HelloState {
     pub greeting: String,
-    pub user_greetings: HashMap<ActorId, String>,
+    pub user_greetings: BTreeMap<ActorId, String>,
     pub counter: u64,
+    pub user_counters: BTreeMap<ActorId, u64>,
+    // Top greeters by count (ties by actor id), updated on every hello
+    pub leaderboard: Vec<(ActorId, u64)>,
+    pub greeting_history: Vec<GreetingRecord>,
+    pub user_history: BTreeMap<ActorId, Vec<GreetingRecord>>,
+    // Set at init; only the owner can manage editors
+    pub owner: ActorId,
+    pub editors: BTreeSet<ActorId>,
+    pub limits: ContentLimits,
+    pub rate_limits: RateLimits,
+    // Timestamp (ms) of each actor's last hello
+    pub last_hello_at: HashMap<ActorId, u64>,
+    // Calls made through each session, keyed by (account, session key); dropped
+    // when the session is removed
+    pub session_calls: HashMap<(ActorId, ActorId), SessionCalls>,
+    // `import_state` is accepted before this block; sealing moves it to the current block
+    pub import_open_until: u32,
+    // Set once an import replaced the history record written at init
+    pub history_imported: bool,
+}
+
+// A zero value disables the corresponding limit
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct RateLimits {
+    pub cooldown_ms: u64,
+    pub max_calls_per_session: u32,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            cooldown_ms: 3_000,
+            max_calls_per_session: 100,
+        }
+    }
+}
+
+// A session keeps its creation time when extended, so only a new session
+// under the same key starts a new count
+#[derive(Debug, Clone, Default)]
+pub struct SessionCalls {
+    pub created_at: u64,
+    pub calls: u32,
+}
+
+// Maximum lengths in bytes of user supplied strings
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct ContentLimits {
+    pub max_name_len: u32,
+    pub max_greeting_len: u32,
+}
+
+impl Default for ContentLimits {
+    fn default() -> Self {
+        Self {
+            max_name_len: 64,
+            max_greeting_len: 256,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum HelloError {
+    EmptyInput,
+    TooLong { max_len: u32 },
+    ControlCharacter,
+    CooldownActive { next_call_at: u64 },
+    SessionQuotaExceeded,
+    Unauthorized,
+}
+
+// Strings are valid UTF-8 once decoded; reject blank, oversized or control characters
+fn validate_input(input: &str, max_len: u32) -> Result<(), HelloError> {
+    if input.trim().is_empty() {
+        return Err(HelloError::EmptyInput);
+    }
+    if input.len() > max_len as usize {
+        return Err(HelloError::TooLong { max_len });
+    }
+    if input.chars().any(char::is_control) {
+        return Err(HelloError::ControlCharacter);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct GreetingRecord {
+    pub author: ActorId,
+    pub message: String,
+    pub timestamp: u64,
 }
 
 impl HelloState {
-    pub fn init_state() {
+    pub fn init_state(limits: ContentLimits) {
+        let greeting = "Hello World from Vara Network!".to_string();
+        let first_record = GreetingRecord {
+            author: msg::source(),
+            message: greeting.clone(),
+            timestamp: exec::block_timestamp(),
+        };
         unsafe {
-            HELLO_STATE = Some(Self {
-                greeting: "Hello World from Vara Network!".to_string(),
-                user_greetings: HashMap::new(),
+            HELLO_STATE = Some(VersionedState::new(STATE_VERSION, Self {
+                greeting,
+                user_greetings: BTreeMap::new(),
                 counter: 0,
-            });
+                user_counters: BTreeMap::new(),
+                leaderboard: Vec::new(),
+                greeting_history: vec![first_record],
+                user_history: BTreeMap::new(),
+                owner: msg::source(),
+                editors: BTreeSet::new(),
+                limits,
+                rate_limits: RateLimits::default(),
+                last_hello_at: HashMap::new(),
+                session_calls: HashMap::new(),
+                import_open_until: exec::block_height().saturating_add(IMPORT_WINDOW_BLOCKS),
+                history_imported: false,
+            }));
         }
     }
 
     pub fn state_mut() -> &'static mut HelloState {
         let state = unsafe { HELLO_STATE.as_mut() };
         debug_assert!(state.is_some(), "The state is not initialized");
-        unsafe { state.unwrap_unchecked() }
+        unsafe { &mut state.unwrap_unchecked().state }
     }
 
     pub fn state_ref() -> &'static HelloState {
         let state = unsafe { HELLO_STATE.as_ref() };
         debug_assert!(state.is_some(), "The state is not initialized");
-        unsafe { state.unwrap_unchecked() }
+        unsafe { &state.unwrap_unchecked().state }
+    }
+
+    pub fn version() -> u32 {
+        let state = unsafe { HELLO_STATE.as_ref() };
+        debug_assert!(state.is_some(), "The state is not initialized");
+        unsafe { state.unwrap_unchecked().version }
+    }
+
+    // Earliest timestamp (ms) at which the actor may say hello again
+    pub fn next_hello_at(&self, actor: &ActorId) -> u64 {
+        self.last_hello_at
+            .get(actor)
+            .map(|last| last.saturating_add(self.rate_limits.cooldown_ms))
+            .unwrap_or_default()
+    }
+
+    // Applies the cooldown and, for session calls, the session quota, then records the call
+    pub fn register_hello(&mut self, actor: ActorId, session: Option<&SessionData>) -> Result<(), HelloError> {
+        let now = exec::block_timestamp();
+        if self.rate_limits.cooldown_ms > 0 {
+            let next_call_at = self.next_hello_at(&actor);
+            if now < next_call_at {
+                return Err(HelloError::CooldownActive { next_call_at });
+            }
+        }
+
+        if let Some(session) = session {
+            let entry = self.session_calls.entry((actor, session.key)).or_default();
+            if entry.created_at != session.created_at {
+                *entry = SessionCalls {
+                    created_at: session.created_at,
+                    calls: 0,
+                };
+            }
+            let quota = self.rate_limits.max_calls_per_session;
+            if quota > 0 && entry.calls >= quota {
+                return Err(HelloError::SessionQuotaExceeded);
+            }
+            entry.calls += 1;
+        }
+
+        self.last_hello_at.insert(actor, now);
+        Ok(())
+    }
+
+    // Counts a hello for the actor, returning their updated count
+    pub fn count_hello(&mut self, actor: ActorId) -> u64 {
+        self.counter += 1;
+        let count = self.user_counters.entry(actor).or_default();
+        *count += 1;
+        let count = *count;
+
+        // Counts only grow, so the actor can only move up or enter the board
+        match self.leaderboard.iter_mut().find(|(greeter, _)| *greeter == actor) {
+            Some(entry) => entry.1 = count,
+            None => self.leaderboard.push((actor, count)),
+        }
+        self.leaderboard
+            .sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        self.leaderboard.truncate(LEADERBOARD_SIZE);
+        count
+    }
+
+    pub fn rebuild_leaderboard(&mut self) {
+        let mut leaderboard: Vec<(ActorId, u64)> = self
+            .user_counters
+            .iter()
+            .map(|(greeter, count)| (*greeter, *count))
+            .collect();
+        leaderboard.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        leaderboard.truncate(LEADERBOARD_SIZE);
+        self.leaderboard = leaderboard;
+    }
+
+    pub fn can_edit(&self, actor: &ActorId) -> bool {
+        self.owner == *actor || self.editors.contains(actor)
+    }
+
+    pub fn record_greeting(&mut self, author: ActorId, message: String) {
+        push_bounded(
+            &mut self.greeting_history,
+            GreetingRecord { author, message, timestamp: exec::block_timestamp() },
+            MAX_GREETING_HISTORY,
+        );
+    }
+
+    pub fn record_user_greeting(&mut self, user: ActorId, message: String) {
+        push_bounded(
+            self.user_history.entry(user).or_default(),
+            GreetingRecord { author: user, message, timestamp: exec::block_timestamp() },
+            MAX_USER_HISTORY,
+        );
     }
 }
 
-#[derive(Encode, Decode, TypeInfo)]
+// `on_session_removed` hook of the session system
+pub fn forget_session(account: ActorId, key: ActorId) {
+    HelloState::state_mut().session_calls.remove(&(account, key));
+}
+
+fn push_bounded(history: &mut Vec<GreetingRecord>, record: GreetingRecord, limit: usize) {
+    if history.len() >= limit {
+        history.remove(0);
+    }
+    history.push(record);
+}
+
+// Returns a page of the history, newest first
+fn history_page(history: &[GreetingRecord], offset: u32, limit: u32) -> Vec<GreetingRecord> {
+    history
+        .iter()
+        .rev()
+        .skip(offset as usize)
+        .take(limit.min(MAX_PAGE_SIZE) as usize)
+        .cloned()
+        .collect()
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
 #[codec(crate = sails_rs::scale_codec)]
 #[scale_info(crate = sails_rs::scale_info)]
 pub enum Events {
-    Hello,
-    PersonalHello(ActorId, String),
+    Hello(ActorId, u64),
+    PersonalHello(ActorId, String, u64),
     GreetingSet(String),
+    EditorGranted(ActorId),
+    EditorRevoked(ActorId),
+    ContentLimitsSet(ContentLimits),
+    RateLimitsSet(RateLimits),
+    StateImported(u32),
+    StateSealed,
 }
 
 #[derive(Encode, Decode, TypeInfo)]
@@ -77,8 +323,8 @@ impl From<HelloState> for IoHelloState {
 pub struct Service;
 
 impl Service {
-    pub fn seed() {
-        HelloState::init_state();
+    pub fn seed(limits: Option<ContentLimits>) {
+        HelloState::init_state(limits.unwrap_or_default());
     }
 }
 
@@ -88,38 +334,100 @@ impl Service {
         Self
     }
 
-    pub fn hello_world(&mut self, session_for_account: Option<ActorId>) -> Events {
+    pub fn hello_world(&mut self, session_for_account: Option<ActorId>) -> Result<Events, HelloError> {
         let msg_src = msg::source();
-        let sessions = Storage::get_session_map();
-        let _actor = get_actor(&sessions, &msg_src, &session_for_account, ActionsForSession::SayHello);
+        let actor = get_actor(&msg_src, &session_for_account, ActionsForSession::SayHello, CallArgs::default());
 
-        HelloState::state_mut().counter += 1;
-        self.emit_event(Events::Hello).expect("Notification failure");
-        Events::Hello
+        let state = HelloState::state_mut();
+        let session = session_for_account.and_then(|account| Storage::get_session_map().get(&(account, msg_src)));
+        state
+            .register_hello(actor, session)
+            .inspect_err(|_| refund_session_call(&msg_src, &session_for_account))?;
+        let count = state.count_hello(actor);
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::SayHello);
+        self.emit_event(Events::Hello(actor, count)).expect("Notification failure");
+        Ok(Events::Hello(actor, count))
     }
 
-    pub fn personal_hello(&mut self, name: String, session_for_account: Option<ActorId>) -> Events {
+    pub fn personal_hello(&mut self, name: String, session_for_account: Option<ActorId>) -> Result<Events, HelloError> {
         let msg_src = msg::source();
-        let sessions = Storage::get_session_map();
-        let actor = get_actor(&sessions, &msg_src, &session_for_account, ActionsForSession::SayPersonalHello);
+        let actor = get_actor(&msg_src, &session_for_account, ActionsForSession::SayPersonalHello, CallArgs::default());
 
-        let message = format!("Hello {} from Vara Network!", name);
         let state = HelloState::state_mut();
+        let session = session_for_account.and_then(|account| Storage::get_session_map().get(&(account, msg_src)));
+        validate_input(&name, state.limits.max_name_len)
+            .and_then(|()| state.register_hello(actor, session))
+            .inspect_err(|_| refund_session_call(&msg_src, &session_for_account))?;
+
+        let message = format!("Hello {name} from Vara Network!");
         state.user_greetings.insert(actor, message.clone());
-        state.counter += 1;
+        state.record_user_greeting(actor, message);
+        let count = state.count_hello(actor);
 
-        self.emit_event(Events::PersonalHello(actor, name.clone())).expect("Notification failure");
-        Events::PersonalHello(actor, name)
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::SayPersonalHello);
+        self.emit_event(Events::PersonalHello(actor, name.clone(), count)).expect("Notification failure");
+        Ok(Events::PersonalHello(actor, name, count))
     }
 
-    pub fn set_greeting(&mut self, new_greeting: String, session_for_account: Option<ActorId>) -> Events {
+    pub fn set_greeting(&mut self, new_greeting: String, session_for_account: Option<ActorId>) -> Result<Events, HelloError> {
         let msg_src = msg::source();
-        let sessions = Storage::get_session_map();
-        let _actor = get_actor(&sessions, &msg_src, &session_for_account, ActionsForSession::SetGreeting);
+        let actor = get_actor(&msg_src, &session_for_account, ActionsForSession::SetGreeting, CallArgs::default());
+
+        let state = HelloState::state_mut();
+        if !state.can_edit(&actor) {
+            refund_session_call(&msg_src, &session_for_account);
+            return Err(HelloError::Unauthorized);
+        }
+        validate_input(&new_greeting, state.limits.max_greeting_len)
+            .inspect_err(|_| refund_session_call(&msg_src, &session_for_account))?;
 
-        HelloState::state_mut().greeting = new_greeting.clone();
+        state.greeting = new_greeting.clone();
+        state.record_greeting(actor, new_greeting.clone());
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::SetGreeting);
         self.emit_event(Events::GreetingSet(new_greeting.clone())).expect("Notification failure");
-        Events::GreetingSet(new_greeting)
+        Ok(Events::GreetingSet(new_greeting))
+    }
+
+    pub fn set_content_limits(&mut self, limits: ContentLimits) -> Events {
+        let state = HelloState::state_mut();
+        assert_eq!(state.owner, msg::source(), "Not owner");
+        state.limits = limits.clone();
+        self.emit_event(Events::ContentLimitsSet(limits.clone())).expect("Notification failure");
+        Events::ContentLimitsSet(limits)
+    }
+
+    pub fn grant_editor(&mut self, to: ActorId, session_for_account: Option<ActorId>) -> Events {
+        let msg_src = msg::source();
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::GrantEditor,
+            CallArgs::default().with_grantee(to),
+        );
+
+        let state = HelloState::state_mut();
+        assert_eq!(state.owner, actor, "Not owner");
+        state.editors.insert(to);
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::GrantEditor);
+        self.emit_event(Events::EditorGranted(to)).expect("Notification failure");
+        Events::EditorGranted(to)
+    }
+
+    pub fn revoke_editor(&mut self, from: ActorId, session_for_account: Option<ActorId>) -> Events {
+        let msg_src = msg::source();
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::RevokeEditor,
+            CallArgs::default().with_grantee(from),
+        );
+
+        let state = HelloState::state_mut();
+        assert_eq!(state.owner, actor, "Not owner");
+        state.editors.remove(&from);
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::RevokeEditor);
+        self.emit_event(Events::EditorRevoked(from)).expect("Notification failure");
+        Events::EditorRevoked(from)
     }
 
     pub fn query_greeting(&self) -> String {
@@ -134,6 +442,95 @@ impl Service {
         HelloState::state_ref().counter
     }
 
+    pub fn query_user_counter(&self, user: ActorId) -> u64 {
+        HelloState::state_ref().user_counters.get(&user).copied().unwrap_or_default()
+    }
+
+    /// Returns up to `limit` greeters with the most hellos
+    pub fn query_top_greeters(&self, limit: u32) -> Vec<(ActorId, u64)> {
+        HelloState::state_ref()
+            .leaderboard
+            .iter()
+            .take(limit.min(MAX_PAGE_SIZE) as usize)
+            .copied()
+            .collect()
+    }
+
+    /// Returns a page of global greetings, newest first
+    pub fn query_greeting_history(&self, offset: u32, limit: u32) -> Vec<GreetingRecord> {
+        history_page(&HelloState::state_ref().greeting_history, offset, limit)
+    }
+
+    /// Returns a page of a user's personal greetings, newest first
+    pub fn query_user_history(&self, user: ActorId, offset: u32, limit: u32) -> Vec<GreetingRecord> {
+        HelloState::state_ref()
+            .user_history
+            .get(&user)
+            .map(|history| history_page(history, offset, limit))
+            .unwrap_or_default()
+    }
+
+    pub fn set_rate_limits(&mut self, rate_limits: RateLimits) -> Events {
+        let state = HelloState::state_mut();
+        assert_eq!(state.owner, msg::source(), "Not owner");
+        state.rate_limits = rate_limits.clone();
+        self.emit_event(Events::RateLimitsSet(rate_limits.clone())).expect("Notification failure");
+        Events::RateLimitsSet(rate_limits)
+    }
+
+    pub fn query_rate_limits(&self) -> RateLimits {
+        HelloState::state_ref().rate_limits.clone()
+    }
+
+    /// Returns the timestamp (ms) from which the user may say hello again
+    pub fn query_next_call_at(&self, user: ActorId) -> u64 {
+        HelloState::state_ref().next_hello_at(&user)
+    }
+
+    pub fn query_content_limits(&self) -> ContentLimits {
+        HelloState::state_ref().limits.clone()
+    }
+
+    pub fn query_owner(&self) -> ActorId {
+        HelloState::state_ref().owner
+    }
+
+    pub fn query_editors(&self) -> Vec<ActorId> {
+        HelloState::state_ref().editors.iter().copied().collect()
+    }
+
+    /// Returns a page of the exported state, including sessions (owner only)
+    pub fn export_state(&self, offset: u32, limit: u32) -> StateEnvelope {
+        let state = HelloState::state_ref();
+        assert_eq!(state.owner, msg::source(), "Not owner");
+        snapshot::export_page(state, Storage::get_session_map(), offset, limit)
+    }
+
+    /// Applies a chunk exported by a previous deployment, until the program is
+    /// sealed or the import window ends (owner only)
+    pub fn import_state(&mut self, chunk: StateEnvelope) -> Result<Events, MigrationError> {
+        let state = HelloState::state_mut();
+        assert_eq!(state.owner, msg::source(), "Not owner");
+        assert!(exec::block_height() < state.import_open_until, "State is sealed");
+
+        let entries = snapshot::import_chunk(state, Storage::get_session_map_mut(), chunk)?;
+        self.emit_event(Events::StateImported(entries)).expect("Notification failure");
+        Ok(Events::StateImported(entries))
+    }
+
+    /// Closes the import window for good (owner only)
+    pub fn seal_state(&mut self) -> Events {
+        let state = HelloState::state_mut();
+        assert_eq!(state.owner, msg::source(), "Not owner");
+        state.import_open_until = state.import_open_until.min(exec::block_height());
+        self.emit_event(Events::StateSealed).expect("Notification failure");
+        Events::StateSealed
+    }
+
+    pub fn query_state_version(&self) -> u32 {
+        HelloState::version()
+    }
+
     pub fn query_state(&self) -> IoHelloState {
         HelloState::state_ref().clone().into()
     }
@@ -146,34 +543,6 @@ pub enum ActionsForSession {
     SayHello,
     SayPersonalHello,
     SetGreeting,
+    GrantEditor,
+    RevokeEditor,
 }
-
-fn get_actor(
-    session_map: &HashMap<ActorId, SessionData>,
-    msg_source: &ActorId,
-    session_for_account: &Option<ActorId>,
-    actions_for_session: ActionsForSession,
-) -> ActorId {
-    match session_for_account {
-        Some(account) => {
-            let session = session_map
-                .get(account)
-                .expect("No valid session for this account");
-
-            assert!(
-                session.expires > exec::block_timestamp(),
-                "Session expired"
-            );
-            assert!(
-                session.allowed_actions.contains(&actions_for_session),
-                "Action not allowed"
-            );
-            assert_eq!(
-                session.key, *msg_source,
-                "Sender not authorized for session"
-            );
-            *account
-        }
-        None => *msg_source,
-    }
-}
\ No newline at end of file
@@ -0,0 +1,140 @@
+// Snapshot of the greeting state: greeting, limits, editors, per-user greetings
+// and counters, both histories and sessions. The leaderboard is rebuilt on import.
+
+use sails_rs::prelude::*;
+
+use super::migration::{migrate, MigrationError, StateEnvelope, STATE_VERSION};
+use super::service::{ContentLimits, GreetingRecord, HelloState, RateLimits};
+use crate::{SessionData, SessionMap};
+
+pub const MAX_EXPORT_PAGE_SIZE: u32 = 200;
+
+// One item of the exported state. The owner stays the deployer of the new
+// program, and per-call rate limiting data is not carried over.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum StateEntry {
+    Greeting(String),
+    Counter(u64),
+    ContentLimits(ContentLimits),
+    RateLimits(RateLimits),
+    Editor(ActorId),
+    UserGreeting(ActorId, String),
+    UserCounter(ActorId, u64),
+    GreetingHistory(GreetingRecord),
+    UserHistory(ActorId, GreetingRecord),
+    Session(ActorId, SessionData),
+}
+
+// Every entry of the state in key order, so pages can be fetched one by one
+pub fn state_entries<'a>(
+    state: &'a HelloState,
+    sessions: &'a SessionMap,
+) -> impl Iterator<Item = StateEntry> + 'a {
+    let singles = [
+        StateEntry::Greeting(state.greeting.clone()),
+        StateEntry::Counter(state.counter),
+        StateEntry::ContentLimits(state.limits.clone()),
+        StateEntry::RateLimits(state.rate_limits.clone()),
+    ];
+
+    let editors = state.editors.iter().map(|editor| StateEntry::Editor(*editor));
+    let user_greetings = state
+        .user_greetings
+        .iter()
+        .map(|(user, greeting)| StateEntry::UserGreeting(*user, greeting.clone()));
+    let user_counters = state
+        .user_counters
+        .iter()
+        .map(|(user, count)| StateEntry::UserCounter(*user, *count));
+    let greeting_history = state
+        .greeting_history
+        .iter()
+        .map(|record| StateEntry::GreetingHistory(record.clone()));
+    let user_history = state.user_history.iter().flat_map(|(user, records)| {
+        records
+            .iter()
+            .map(move |record| StateEntry::UserHistory(*user, record.clone()))
+    });
+    let session_entries = sessions
+        .iter()
+        .map(|((account, _), session)| StateEntry::Session(*account, session.clone()));
+
+    singles
+        .into_iter()
+        .chain(editors)
+        .chain(user_greetings)
+        .chain(user_counters)
+        .chain(greeting_history)
+        .chain(user_history)
+        .chain(session_entries)
+}
+
+pub fn export_page(
+    state: &HelloState,
+    sessions: &SessionMap,
+    offset: u32,
+    limit: u32,
+) -> StateEnvelope {
+    let entries: Vec<StateEntry> = state_entries(state, sessions)
+        .skip(offset as usize)
+        .take(limit.min(MAX_EXPORT_PAGE_SIZE) as usize)
+        .collect();
+    StateEnvelope {
+        version: STATE_VERSION,
+        payload: entries.encode(),
+    }
+}
+
+// Brings the chunk to the current layout and applies it, returning the number of
+// entries. History records already present are skipped, so a chunk can be sent again.
+pub fn import_chunk(
+    state: &mut HelloState,
+    sessions: &mut SessionMap,
+    chunk: StateEnvelope,
+) -> Result<u32, MigrationError> {
+    let chunk = migrate(chunk)?;
+    let entries = Vec::<StateEntry>::decode(&mut chunk.payload.as_slice())
+        .map_err(|_| MigrationError::InvalidPayload(chunk.version))?;
+
+    let count = entries.len() as u32;
+    for entry in entries {
+        match entry {
+            StateEntry::Greeting(greeting) => state.greeting = greeting,
+            StateEntry::Counter(counter) => state.counter = counter,
+            StateEntry::ContentLimits(limits) => state.limits = limits,
+            StateEntry::RateLimits(rate_limits) => state.rate_limits = rate_limits,
+            StateEntry::Editor(editor) => {
+                state.editors.insert(editor);
+            }
+            StateEntry::UserGreeting(user, greeting) => {
+                state.user_greetings.insert(user, greeting);
+            }
+            StateEntry::UserCounter(user, count) => {
+                state.user_counters.insert(user, count);
+            }
+            StateEntry::GreetingHistory(record) => {
+                // Imported history replaces the record written at init
+                if !state.history_imported {
+                    state.greeting_history.clear();
+                    state.history_imported = true;
+                }
+                if !state.greeting_history.contains(&record) {
+                    state.greeting_history.push(record);
+                }
+            }
+            StateEntry::UserHistory(user, record) => {
+                let history = state.user_history.entry(user).or_default();
+                if !history.contains(&record) {
+                    history.push(record);
+                }
+            }
+            StateEntry::Session(account, session) => {
+                sessions.insert((account, session.key), session);
+            }
+        }
+    }
+    state.rebuild_leaderboard();
+    Ok(count)
+}
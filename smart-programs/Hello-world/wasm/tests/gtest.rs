@@ -0,0 +1,389 @@
+use app::{
+    services::{
+        migration::{MigrationError, StateEnvelope},
+        service::{
+            ActionsForSession, ContentLimits, Events, GreetingRecord, HelloError, IoHelloState, RateLimits,
+            MAX_GREETING_HISTORY, MAX_PAGE_SIZE, MAX_USER_HISTORY,
+        },
+        snapshot::StateEntry,
+    },
+    Config, SessionScope, SignatureData,
+};
+use gtest::{Program, System};
+use gtest_helpers::{call, deploy, reply_to};
+use sails_rs::prelude::*;
+
+const OWNER: u64 = 10;
+const ALICE: u64 = 11;
+const KEY: u64 = 12;
+
+fn init_program(system: &System) -> Program<'_> {
+    let config = Config {
+        gas_to_delete_session: 10_000_000_000,
+        minimum_session_duration_ms: 180_000,
+        ms_per_block: 3_000,
+    };
+    deploy(system, wasm::WASM_BINARY, OWNER, &[OWNER, ALICE, KEY], (config, None::<ContentLimits>))
+}
+
+fn service_call(method: &str, args: impl Encode) -> Vec<u8> {
+    call("Service", method, args)
+}
+
+fn set_greeting(system: &System, program: &Program, from: u64, greeting: &str) -> Option<Result<Events, HelloError>> {
+    program.send_bytes(from, service_call("SetGreeting", (String::from(greeting), None::<ActorId>)));
+    let result = system.run_next_block();
+    reply_to(&result, from)
+}
+
+// `account` opens a session for KEY that allows `max_calls` calls of `allowed_actions`
+fn create_session(
+    system: &System,
+    program: &Program,
+    account: u64,
+    allowed_actions: Vec<ActionsForSession>,
+    max_calls: Option<u32>,
+) {
+    let signature_data = SignatureData {
+        key: ActorId::from(KEY),
+        duration: 180_000,
+        allowed_actions,
+        spend_limits: vec![],
+        max_calls,
+        scope: SessionScope::default(),
+        allow_renewal: false,
+    };
+    let id = program.send_bytes(
+        account,
+        ["Session".encode(), "CreateSession".encode(), (signature_data, None::<Vec<u8>>).encode()].concat(),
+    );
+    assert!(system.run_next_block().succeed.contains(&id));
+}
+
+fn query<T: Decode>(system: &System, program: &Program, method: &str, args: impl Encode) -> T {
+    program.send_bytes(OWNER, service_call(method, args));
+    let result = system.run_next_block();
+    reply_to(&result, OWNER).expect("No reply to query")
+}
+
+fn greeting(system: &System, program: &Program) -> String {
+    query(system, program, "QueryGreeting", ())
+}
+
+#[test]
+fn only_owner_and_editors_can_set_greeting() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    assert_eq!(
+        set_greeting(&system, &program, ALICE, "Hi"),
+        Some(Err(HelloError::Unauthorized))
+    );
+    assert_eq!(greeting(&system, &program), "Hello World from Vara Network!");
+
+    program.send_bytes(OWNER, service_call("GrantEditor", (ActorId::from(ALICE), None::<ActorId>)));
+    system.run_next_block();
+    assert_eq!(
+        set_greeting(&system, &program, ALICE, "Hi"),
+        Some(Ok(Events::GreetingSet(String::from("Hi"))))
+    );
+    assert_eq!(greeting(&system, &program), "Hi");
+}
+
+#[test]
+fn failed_session_call_does_not_consume_the_quota() {
+    let system = System::new();
+    let program = init_program(&system);
+    create_session(&system, &program, ALICE, vec![ActionsForSession::SetGreeting], Some(1));
+    let through_session = service_call("SetGreeting", (String::from("Hi"), Some(ActorId::from(ALICE))));
+
+    // ALICE is not an editor yet, so the call fails without using the only call
+    program.send_bytes(KEY, through_session.clone());
+    let result = system.run_next_block();
+    assert_eq!(
+        reply_to::<Result<Events, HelloError>>(&result, KEY),
+        Some(Err(HelloError::Unauthorized))
+    );
+
+    program.send_bytes(OWNER, service_call("GrantEditor", (ActorId::from(ALICE), None::<ActorId>)));
+    system.run_next_block();
+    program.send_bytes(KEY, through_session.clone());
+    let result = system.run_next_block();
+    assert_eq!(
+        reply_to::<Result<Events, HelloError>>(&result, KEY),
+        Some(Ok(Events::GreetingSet(String::from("Hi"))))
+    );
+
+    let id = program.send_bytes(KEY, through_session);
+    assert!(system.run_next_block().failed.contains(&id));
+}
+
+// What a migration has to carry over, as seen through the queries
+#[derive(Debug, PartialEq)]
+struct Observed {
+    greeting: String,
+    counter: u64,
+    user_greetings: Vec<(ActorId, String)>,
+    editors: Vec<ActorId>,
+    top_greeters: Vec<(ActorId, u64)>,
+    greeting_history: Vec<GreetingRecord>,
+}
+
+fn observe(system: &System, program: &Program) -> Observed {
+    let state: IoHelloState = query(system, program, "QueryState", ());
+    Observed {
+        greeting: state.greeting,
+        counter: state.counter,
+        user_greetings: state.user_greetings,
+        editors: query(system, program, "QueryEditors", ()),
+        top_greeters: query(system, program, "QueryTopGreeters", 10_u32),
+        greeting_history: query(system, program, "QueryGreetingHistory", (0_u32, 50_u32)),
+    }
+}
+
+fn import_state(system: &System, program: &Program, chunk: StateEnvelope) -> Option<Result<Events, MigrationError>> {
+    program.send_bytes(OWNER, service_call("ImportState", chunk));
+    let result = system.run_next_block();
+    reply_to(&result, OWNER)
+}
+
+#[test]
+fn exported_state_is_restored_by_import() {
+    // Exported in small pages from a program in use
+    let (chunks, observed) = {
+        let system = System::new();
+        let program = init_program(&system);
+        program.send_bytes(OWNER, service_call("GrantEditor", (ActorId::from(ALICE), None::<ActorId>)));
+        system.run_next_block();
+        assert!(matches!(set_greeting(&system, &program, ALICE, "Hi"), Some(Ok(_))));
+        program.send_bytes(ALICE, service_call("HelloWorld", None::<ActorId>));
+        program.send_bytes(KEY, service_call("PersonalHello", (String::from("Key"), None::<ActorId>)));
+        system.run_next_block();
+
+        let mut chunks = Vec::new();
+        loop {
+            let chunk: StateEnvelope = query(&system, &program, "ExportState", (chunks.len() as u32 * 3, 3_u32));
+            let entries = Vec::<StateEntry>::decode(&mut chunk.payload.as_slice()).expect("Invalid chunk");
+            if entries.is_empty() {
+                break;
+            }
+            chunks.push(chunk);
+        }
+        (chunks, observe(&system, &program))
+    };
+    assert!(chunks.len() > 1);
+
+    let system = System::new();
+    let program = init_program(&system);
+    for chunk in chunks.clone() {
+        let entries = Vec::<StateEntry>::decode(&mut chunk.payload.as_slice()).unwrap().len() as u32;
+        assert_eq!(
+            import_state(&system, &program, chunk),
+            Some(Ok(Events::StateImported(entries)))
+        );
+    }
+    assert_eq!(observe(&system, &program), observed);
+
+    // Once sealed, no chunk is accepted anymore
+    program.send_bytes(OWNER, service_call("SealState", ()));
+    system.run_next_block();
+    let id = program.send_bytes(OWNER, service_call("ImportState", chunks[0].clone()));
+    assert!(system.run_next_block().failed.contains(&id));
+}
+
+fn set_rate_limits(system: &System, program: &Program, cooldown_ms: u64, max_calls_per_session: u32) {
+    let rate_limits = RateLimits {
+        cooldown_ms,
+        max_calls_per_session,
+    };
+    let id = program.send_bytes(OWNER, service_call("SetRateLimits", rate_limits));
+    assert!(system.run_next_block().succeed.contains(&id));
+}
+
+fn messages(records: Vec<GreetingRecord>) -> Vec<String> {
+    records.into_iter().map(|record| record.message).collect()
+}
+
+#[test]
+fn greeting_history_is_paged_newest_first_and_keeps_the_latest() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    // Together with the greeting set at init, five records more than the history keeps
+    let greetings = MAX_GREETING_HISTORY + 4;
+    for i in 0..greetings {
+        assert!(matches!(set_greeting(&system, &program, OWNER, &format!("G{i}")), Some(Ok(_))));
+    }
+
+    let page = |offset: u32, limit: u32| -> Vec<String> {
+        messages(query(&system, &program, "QueryGreetingHistory", (offset, limit)))
+    };
+    let newest = greetings - 1;
+    assert_eq!(page(0, 3), [newest, newest - 1, newest - 2].map(|i| format!("G{i}")));
+    assert_eq!(page(2, 2), [newest - 2, newest - 3].map(|i| format!("G{i}")));
+    assert_eq!(page(0, 200).len(), MAX_PAGE_SIZE as usize);
+
+    // The init greeting and the oldest records were dropped
+    let last = MAX_GREETING_HISTORY as u32 - 2;
+    let oldest_kept = greetings - MAX_GREETING_HISTORY;
+    assert_eq!(page(last, 10), [oldest_kept + 1, oldest_kept].map(|i| format!("G{i}")));
+    assert!(page(MAX_GREETING_HISTORY as u32, 10).is_empty());
+}
+
+#[test]
+fn user_history_is_paged_newest_first_and_keeps_the_latest() {
+    let system = System::new();
+    let program = init_program(&system);
+    set_rate_limits(&system, &program, 0, 0);
+
+    let hellos = MAX_USER_HISTORY + 2;
+    for i in 0..hellos {
+        program.send_bytes(ALICE, service_call("PersonalHello", (format!("N{i}"), None::<ActorId>)));
+        system.run_next_block();
+    }
+
+    let page = |offset: u32, limit: u32| -> Vec<String> {
+        messages(query(&system, &program, "QueryUserHistory", (ActorId::from(ALICE), offset, limit)))
+    };
+    let hello = |i: usize| format!("Hello N{i} from Vara Network!");
+    assert_eq!(page(0, 2), [hello(hellos - 1), hello(hellos - 2)]);
+    assert_eq!(page(MAX_USER_HISTORY as u32 - 1, 5), [hello(2)]);
+    assert_eq!(page(0, MAX_PAGE_SIZE).len(), MAX_USER_HISTORY);
+    assert!(messages(query(&system, &program, "QueryUserHistory", (ActorId::from(KEY), 0_u32, 5_u32))).is_empty());
+}
+
+fn personal_hello(system: &System, program: &Program, from: u64, name: &str) -> Option<Result<Events, HelloError>> {
+    program.send_bytes(from, service_call("PersonalHello", (String::from(name), None::<ActorId>)));
+    let result = system.run_next_block();
+    reply_to(&result, from)
+}
+
+#[test]
+fn blank_input_is_rejected() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    assert_eq!(set_greeting(&system, &program, OWNER, ""), Some(Err(HelloError::EmptyInput)));
+    assert_eq!(set_greeting(&system, &program, OWNER, " \t "), Some(Err(HelloError::EmptyInput)));
+    assert_eq!(personal_hello(&system, &program, ALICE, "  "), Some(Err(HelloError::EmptyInput)));
+    assert_eq!(greeting(&system, &program), "Hello World from Vara Network!");
+}
+
+#[test]
+fn input_over_the_content_limits_is_rejected() {
+    let system = System::new();
+    let program = init_program(&system);
+    let limits = ContentLimits::default();
+
+    let greeting_at_limit = "g".repeat(limits.max_greeting_len as usize);
+    assert_eq!(
+        set_greeting(&system, &program, OWNER, &format!("{greeting_at_limit}g")),
+        Some(Err(HelloError::TooLong { max_len: limits.max_greeting_len }))
+    );
+    let name_at_limit = "n".repeat(limits.max_name_len as usize);
+    assert_eq!(
+        personal_hello(&system, &program, ALICE, &format!("{name_at_limit}n")),
+        Some(Err(HelloError::TooLong { max_len: limits.max_name_len }))
+    );
+
+    // The limits count bytes, and inputs right at them are accepted
+    assert!(matches!(set_greeting(&system, &program, OWNER, &greeting_at_limit), Some(Ok(_))));
+    assert_eq!(
+        personal_hello(&system, &program, ALICE, &"é".repeat(limits.max_name_len as usize / 2 + 1)),
+        Some(Err(HelloError::TooLong { max_len: limits.max_name_len }))
+    );
+    assert!(matches!(personal_hello(&system, &program, ALICE, &name_at_limit), Some(Ok(_))));
+}
+
+#[test]
+fn control_characters_are_rejected() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    assert_eq!(set_greeting(&system, &program, OWNER, "Hi\nthere"), Some(Err(HelloError::ControlCharacter)));
+    assert_eq!(personal_hello(&system, &program, ALICE, "Al\u{7}ice"), Some(Err(HelloError::ControlCharacter)));
+    assert_eq!(greeting(&system, &program), "Hello World from Vara Network!");
+}
+
+fn hello(system: &System, program: &Program, from: u64, session_for_account: Option<u64>) -> Option<Result<Events, HelloError>> {
+    program.send_bytes(from, service_call("HelloWorld", session_for_account.map(ActorId::from)));
+    let result = system.run_next_block();
+    reply_to(&result, from)
+}
+
+#[test]
+fn hellos_within_the_cooldown_are_rejected() {
+    let system = System::new();
+    let program = init_program(&system);
+    set_rate_limits(&system, &program, 10_000, 0);
+
+    assert_eq!(hello(&system, &program, ALICE, None), Some(Ok(Events::Hello(ActorId::from(ALICE), 1))));
+    let next_call_at = system.block_timestamp() + 10_000;
+    assert_eq!(query::<u64>(&system, &program, "QueryNextCallAt", ActorId::from(ALICE)), next_call_at);
+
+    // Three seconds per block: the next blocks are still within the cooldown
+    assert_eq!(hello(&system, &program, ALICE, None), Some(Err(HelloError::CooldownActive { next_call_at })));
+    // The cooldown is per user
+    assert_eq!(hello(&system, &program, KEY, None), Some(Ok(Events::Hello(ActorId::from(KEY), 1))));
+
+    while system.block_timestamp() + 3_000 < next_call_at {
+        system.run_next_block();
+    }
+    assert_eq!(hello(&system, &program, ALICE, None), Some(Ok(Events::Hello(ActorId::from(ALICE), 2))));
+}
+
+#[test]
+fn session_calls_over_the_quota_are_rejected() {
+    let system = System::new();
+    let program = init_program(&system);
+    set_rate_limits(&system, &program, 0, 2);
+    create_session(&system, &program, ALICE, vec![ActionsForSession::SayHello], None);
+
+    for count in 1..=2 {
+        assert_eq!(hello(&system, &program, KEY, Some(ALICE)), Some(Ok(Events::Hello(ActorId::from(ALICE), count))));
+    }
+    assert_eq!(hello(&system, &program, KEY, Some(ALICE)), Some(Err(HelloError::SessionQuotaExceeded)));
+
+    // The quota only bounds the session: the account itself can still say hello
+    assert_eq!(hello(&system, &program, ALICE, None), Some(Ok(Events::Hello(ActorId::from(ALICE), 3))));
+
+    // A new session of the key starts a new count
+    let id = program.send_bytes(ALICE, call("Session", "DeleteSessionKey", ActorId::from(KEY)));
+    assert!(system.run_next_block().succeed.contains(&id));
+    create_session(&system, &program, ALICE, vec![ActionsForSession::SayHello], None);
+    assert_eq!(hello(&system, &program, KEY, Some(ALICE)), Some(Ok(Events::Hello(ActorId::from(ALICE), 4))));
+}
+
+#[test]
+fn leaderboard_ranks_greeters_by_their_counters() {
+    let system = System::new();
+    let program = init_program(&system);
+    set_rate_limits(&system, &program, 0, 0);
+
+    // KEY gets ahead of ALICE, and OWNER catches up with ALICE
+    for (greeter, hellos) in [(ALICE, 2), (KEY, 3), (OWNER, 2)] {
+        for _ in 0..hellos {
+            assert!(matches!(hello(&system, &program, greeter, None), Some(Ok(_))));
+        }
+    }
+    // Personal hellos count as well
+    assert_eq!(
+        personal_hello(&system, &program, ALICE, "Alice"),
+        Some(Ok(Events::PersonalHello(ActorId::from(ALICE), String::from("Alice"), 3)))
+    );
+
+    for (greeter, count) in [(ALICE, 3), (KEY, 3), (OWNER, 2)] {
+        assert_eq!(query::<u64>(&system, &program, "QueryUserCounter", ActorId::from(greeter)), count);
+    }
+    assert_eq!(query::<u64>(&system, &program, "QueryUserCounter", ActorId::from(99)), 0);
+    assert_eq!(query::<u64>(&system, &program, "QueryCounter", ()), 8);
+
+    // Ties are ordered by actor id
+    let top: Vec<(ActorId, u64)> = query(&system, &program, "QueryTopGreeters", 10_u32);
+    assert_eq!(
+        top,
+        vec![(ActorId::from(ALICE), 3), (ActorId::from(KEY), 3), (ActorId::from(OWNER), 2)]
+    );
+    let top: Vec<(ActorId, u64)> = query(&system, &program, "QueryTopGreeters", 1_u32);
+    assert_eq!(top, vec![(ActorId::from(ALICE), 3)]);
+}
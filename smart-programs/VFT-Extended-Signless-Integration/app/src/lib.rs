@@ -4,7 +4,6 @@
 use sails_rs::prelude::*;
 pub mod services;
 use services::service::{ActionsForSession, ExtendedService};
-use session_service::*;
 
 
 session_service::generate_session_system!(ActionsForSession);
@@ -19,12 +18,12 @@ impl Program {
         Self
     }
 
-     #[route("Service")]
+    #[export(route = "Service")]
     pub fn service(&self) -> ExtendedService {
         ExtendedService::new()
     }
 
-    #[route("Session")]
+    #[export(route = "Session")]
     pub fn session(&self) -> SessionService {
         SessionService::new()
     }
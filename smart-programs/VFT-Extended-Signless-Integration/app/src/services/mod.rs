@@ -1 +1,3 @@
-pub mod service;
\ No newline at end of file
+pub mod service;
+pub mod migration;
+pub mod snapshot;
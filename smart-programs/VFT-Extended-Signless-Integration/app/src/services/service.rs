@@ -1,9 +1,8 @@
 
-#![no_std]
 #![allow(static_mut_refs)]
 
 use gstd::{msg, exec};
-use sails_rs::{collections::HashSet, gstd::service, prelude::*};
+use sails_rs::{collections::BTreeSet, gstd::service, gstd::services::Service as _, prelude::*};
 use vft_service::utils;
 use vft_service::{Service as VftService, Storage};
 use vft_service::{
@@ -11,64 +10,29 @@ use vft_service::{
     utils::{Error, Result, *},
 };
 
-pub mod session_service {
-    use super::*;
-    use sails_rs::collections::HashMap;
-
-    #[derive(Clone, Encode, Decode, TypeInfo, Default)]
-    #[codec(crate = sails_rs::scale_codec)]
-    #[scale_info(crate = sails_rs::scale_info)]
-    pub struct SessionData {
-        pub key: ActorId,
-        pub expires: u64,
-        pub allowed_actions: Vec<ActionsForSession>,
-    }
-
-    #[derive(Default)]
-    pub struct SessionStorage {
-        pub session_map: HashMap<ActorId, SessionData>,
-    }
-
-    static mut SESSION_STORAGE: Option<SessionStorage> = None;
-
-    impl SessionStorage {
-        pub fn get_mut() -> &'static mut SessionStorage {
-            unsafe {
-                SESSION_STORAGE
-                    .as_mut()
-                    .expect("Session storage not initialized")
-            }
-        }
-        pub fn get() -> &'static SessionStorage {
-            unsafe {
-                SESSION_STORAGE
-                    .as_ref()
-                    .expect("Session storage not initialized")
-            }
-        }
-        pub fn seed() {
-            unsafe {
-                SESSION_STORAGE = Some(SessionStorage {
-                    session_map: HashMap::new(),
-                });
-            }
-        }
-    }
-    pub fn get_session_map() -> &'static HashMap<ActorId, SessionData> {
-        &SessionStorage::get().session_map
-    }
-}
-
-use session_service::{SessionData, get_session_map};
+use super::migration::{MigrationError, StateEnvelope, VersionedState, IMPORT_WINDOW_BLOCKS, STATE_VERSION};
+use super::snapshot;
+use crate::{get_actor, record_session_call, CallArgs};
 
+// Role sets are ordered so paginated queries return a stable sequence
 #[derive(Default)]
 pub struct ExtendedStorage {
-    minters: HashSet<ActorId>,
-    burners: HashSet<ActorId>,
-    admins: HashSet<ActorId>,
+    pub(crate) minters: BTreeSet<ActorId>,
+    pub(crate) burners: BTreeSet<ActorId>,
+    pub(crate) admins: BTreeSet<ActorId>,
+    // Accounts with a non-zero balance, kept in step with the vft balances
+    pub(crate) holders: BTreeSet<ActorId>,
+    // `import_state` is accepted before this block; sealing moves it to the current block
+    import_open_until: u32,
 }
 
-static mut EXTENDED_STORAGE: Option<ExtendedStorage> = None;
+// Upper bound for a single page returned by paginated queries
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+// SCALE-encoded route the program exposes this service at, `Program::service`
+const SERVICE_ROUTE: &[u8] = b"\x1cService";
+
+static mut EXTENDED_STORAGE: Option<VersionedState<ExtendedStorage>> = None;
 
 #[derive(Encode, Decode, TypeInfo)]
 #[codec(crate = sails_rs::scale_codec)]
@@ -76,6 +40,8 @@ static mut EXTENDED_STORAGE: Option<ExtendedStorage> = None;
 pub enum Event {
     Minted { to: ActorId, value: U256 },
     Burned { from: ActorId, value: U256 },
+    StateImported { entries: u32 },
+    StateSealed,
 }
 
 #[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
@@ -104,13 +70,14 @@ impl ExtendedService {
     pub fn seed(name: String, symbol: String, decimals: u8) -> Self {
         let admin = msg::source();
         unsafe {
-            EXTENDED_STORAGE = Some(ExtendedStorage {
+            EXTENDED_STORAGE = Some(VersionedState::new(STATE_VERSION, ExtendedStorage {
                 admins: [admin].into(),
                 minters: [admin].into(),
                 burners: [admin].into(),
-            });
+                holders: BTreeSet::new(),
+                import_open_until: exec::block_height().saturating_add(IMPORT_WINDOW_BLOCKS),
+            }));
         };
-        session_service::SessionStorage::seed();
         ExtendedService {
             vft: <VftService>::seed(name, symbol, decimals),
         }
@@ -119,50 +86,59 @@ impl ExtendedService {
  
     pub fn get_mut(&mut self) -> &'static mut ExtendedStorage {
         unsafe {
-            EXTENDED_STORAGE
+            &mut EXTENDED_STORAGE
                 .as_mut()
                 .expect("Extended vft is not initialized")
+                .state
         }
     }
    
 
     pub fn get(&self) -> &'static ExtendedStorage {
+        unsafe {
+            &EXTENDED_STORAGE
+                .as_ref()
+                .expect("Extended vft is not initialized")
+                .state
+        }
+    }
+
+    // Adds or drops `account` from the holder index depending on its current balance
+    pub(crate) fn sync_holder(&mut self, account: ActorId) {
+        if funcs::balance_of(Storage::balances(), account).is_zero() {
+            self.get_mut().holders.remove(&account);
+        } else {
+            self.get_mut().holders.insert(account);
+        }
+    }
+
+    pub fn version(&self) -> u32 {
         unsafe {
             EXTENDED_STORAGE
                 .as_ref()
                 .expect("Extended vft is not initialized")
+                .version
         }
     }
 }
 
 
-fn get_actor(
-    session_map: &sails_rs::collections::HashMap<ActorId, SessionData>,
-    msg_source: &ActorId,
-    session_for_account: &Option<ActorId>,
-    action: ActionsForSession,
-) -> ActorId {
-    match session_for_account {
-        Some(account) => {
-            let session = session_map
-                .get(account)
-                .expect("No valid session for this account");
-
-            assert!(
-                session.expires > exec::block_timestamp(),
-                "Session expired"
-            );
-            assert!(
-                session.allowed_actions.contains(&action),
-                "Action not allowed"
-            );
-            assert_eq!(
-                session.key, *msg_source,
-                "Sender not authorized for session"
-            );
-            *account
-        }
-        None => *msg_source,
+impl Default for ExtendedService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Token amount as checked by session limits; amounts above u128 hit any limit
+fn session_value(value: U256) -> u128 {
+    u128::try_from(value).unwrap_or(u128::MAX)
+}
+
+// Charges a session mint or burn against the session spend limits, keyed by this
+// program's id and counted in token units
+fn charge_session(session_for_account: &Option<ActorId>, value: U256) {
+    if let Some(account) = session_for_account {
+        crate::Storage::spend(account, &msg::source(), exec::program_id(), session_value(value));
     }
 }
 
@@ -176,28 +152,67 @@ impl ExtendedService {
         }
     }
 
-   pub fn approve(&mut self, spender: ActorId, value: U256, session_for_account: Option<ActorId>) {
-    let msg_src = msg::source();
-    let owner = get_actor(get_session_map(), &msg_src, &session_for_account, ActionsForSession::Approve);
-
-    let ok = self.vft.approve(spender, value); 
-    assert!(ok, "Approval failed");
-}
+    // Approves `spender` on behalf of the session's account, or the caller without a session
+    pub fn approve(&mut self, spender: ActorId, value: U256, session_for_account: Option<ActorId>) {
+        let msg_src = msg::source();
+        let owner = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::Approve,
+            CallArgs::default().with_recipient(spender).with_value(session_value(value)),
+        );
+
+        let mutated = funcs::approve(Storage::allowances(), owner, spender, value);
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::Approve);
+        // The standard vft Approval event, emitted by the base service under this route
+        if mutated {
+            self.vft
+                .clone()
+                .expose(msg::id(), SERVICE_ROUTE)
+                .emit_event(vft_service::Event::Approval { owner, spender, value })
+                .expect("Notification Error");
+        }
+    }
 
+    pub fn transfer(&mut self, to: ActorId, value: U256) -> bool {
+        let from = msg::source();
+        let mutated = self.vft.transfer(to, value);
+        if mutated {
+            self.sync_holder(from);
+            self.sync_holder(to);
+        }
+        mutated
+    }
 
+    pub fn transfer_from(&mut self, from: ActorId, to: ActorId, value: U256) -> bool {
+        let mutated = self.vft.transfer_from(from, to, value);
+        if mutated {
+            self.sync_holder(from);
+            self.sync_holder(to);
+        }
+        mutated
+    }
 
     pub fn mint(&mut self, to: ActorId, value: U256, session_for_account: Option<ActorId>) -> bool {
         let msg_src = msg::source();
-        let actor = get_actor(get_session_map(), &msg_src, &session_for_account, ActionsForSession::Mint);
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::Mint,
+            CallArgs::default().with_recipient(to).with_value(session_value(value)),
+        );
 
         if !self.get().minters.contains(&actor) {
             panic!("Not allowed to mint")
         };
+        charge_session(&session_for_account, value);
 
         let mutated = utils::panicking(|| {
              mint(Storage::balances(), Storage::total_supply(), to, value)
         });
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::Mint);
         if mutated {
+            self.sync_holder(to);
             self.emit_event(Event::Minted { to, value })
                 .expect("Notification Error");
         }
@@ -207,16 +222,28 @@ impl ExtendedService {
 
     pub fn burn(&mut self, from: ActorId, value: U256, session_for_account: Option<ActorId>) -> bool {
         let msg_src = msg::source();
-        let actor = get_actor(get_session_map(), &msg_src, &session_for_account, ActionsForSession::Burn);
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::Burn,
+            CallArgs::default().with_value(session_value(value)),
+        );
 
         if !self.get().burners.contains(&actor) {
             panic!("Not allowed to burn")
         };
+        // A session only burns the tokens of the account it acts for
+        if session_for_account.is_some() {
+            assert_eq!(from, actor, "Session can only burn the account's tokens");
+        }
+        charge_session(&session_for_account, value);
 
         let mutated = utils::panicking(|| {
             burn(Storage::balances(), Storage::total_supply(), from, value)
         });
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::Burn);
         if mutated {
+            self.sync_holder(from);
             self.emit_event(Event::Burned { from, value })
                 .expect("Notification Error");
         }
@@ -225,63 +252,174 @@ impl ExtendedService {
 
     pub fn grant_admin_role(&mut self, to: ActorId, session_for_account: Option<ActorId>) {
         let msg_src = msg::source();
-        let actor = get_actor(get_session_map(), &msg_src, &session_for_account, ActionsForSession::GrantAdmin);
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::GrantAdmin,
+            CallArgs::default().with_grantee(to),
+        );
         self.ensure_is_admin_actor(actor);
         self.get_mut().admins.insert(to);
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::GrantAdmin);
     }
   
 
     pub fn grant_minter_role(&mut self, to: ActorId, session_for_account: Option<ActorId>) {
         let msg_src = msg::source();
-        let actor = get_actor(get_session_map(), &msg_src, &session_for_account, ActionsForSession::GrantMinter);
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::GrantMinter,
+            CallArgs::default().with_grantee(to),
+        );
         self.ensure_is_admin_actor(actor);
         self.get_mut().minters.insert(to);
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::GrantMinter);
     }
 
 
     pub fn grant_burner_role(&mut self, to: ActorId, session_for_account: Option<ActorId>) {
         let msg_src = msg::source();
-        let actor = get_actor(get_session_map(), &msg_src, &session_for_account, ActionsForSession::GrantBurner);
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::GrantBurner,
+            CallArgs::default().with_grantee(to),
+        );
         self.ensure_is_admin_actor(actor);
         self.get_mut().burners.insert(to);
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::GrantBurner);
     }
 
 
     pub fn revoke_admin_role(&mut self, from: ActorId, session_for_account: Option<ActorId>) {
         let msg_src = msg::source();
-        let actor = get_actor(get_session_map(), &msg_src, &session_for_account, ActionsForSession::RevokeAdmin);
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::RevokeAdmin,
+            CallArgs::default().with_grantee(from),
+        );
         self.ensure_is_admin_actor(actor);
         self.get_mut().admins.remove(&from);
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::RevokeAdmin);
     }
 
 
     pub fn revoke_minter_role(&mut self, from: ActorId, session_for_account: Option<ActorId>) {
         let msg_src = msg::source();
-        let actor = get_actor(get_session_map(), &msg_src, &session_for_account, ActionsForSession::RevokeMinter);
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::RevokeMinter,
+            CallArgs::default().with_grantee(from),
+        );
         self.ensure_is_admin_actor(actor);
         self.get_mut().minters.remove(&from);
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::RevokeMinter);
     }
 
 
     pub fn revoke_burner_role(&mut self, from: ActorId, session_for_account: Option<ActorId>) {
         let msg_src = msg::source();
-        let actor = get_actor(get_session_map(), &msg_src, &session_for_account, ActionsForSession::RevokeBurner);
+        let actor = get_actor(
+            &msg_src,
+            &session_for_account,
+            ActionsForSession::RevokeBurner,
+            CallArgs::default().with_grantee(from),
+        );
         self.ensure_is_admin_actor(actor);
         self.get_mut().burners.remove(&from);
+        record_session_call(&msg_src, &session_for_account, ActionsForSession::RevokeBurner);
     }
 
  
     pub fn minters(&self) -> Vec<ActorId> {
-        self.get().minters.clone().into_iter().collect()
+        self.get().minters.iter().copied().collect()
     }
 
 
     pub fn burners(&self) -> Vec<ActorId> {
-        self.get().burners.clone().into_iter().collect()
+        self.get().burners.iter().copied().collect()
     }
 
     pub fn admins(&self) -> Vec<ActorId> {
-        self.get().admins.clone().into_iter().collect()
+        self.get().admins.iter().copied().collect()
+    }
+
+    /// Returns a page of minters ordered by actor id
+    pub fn minters_page(&self, offset: u32, limit: u32) -> Vec<ActorId> {
+        paginate(self.get().minters.iter().copied(), offset, limit)
+    }
+
+    /// Returns a page of burners ordered by actor id
+    pub fn burners_page(&self, offset: u32, limit: u32) -> Vec<ActorId> {
+        paginate(self.get().burners.iter().copied(), offset, limit)
+    }
+
+    /// Returns a page of admins ordered by actor id
+    pub fn admins_page(&self, offset: u32, limit: u32) -> Vec<ActorId> {
+        paginate(self.get().admins.iter().copied(), offset, limit)
+    }
+
+    pub fn minters_count(&self) -> u32 {
+        self.get().minters.len() as u32
+    }
+
+    pub fn burners_count(&self) -> u32 {
+        self.get().burners.len() as u32
+    }
+
+    pub fn admins_count(&self) -> u32 {
+        self.get().admins.len() as u32
+    }
+
+    /// Returns a page of token holders and their balances ordered by actor id
+    pub fn holders(&self, offset: u32, limit: u32) -> Vec<(ActorId, U256)> {
+        let balances = Storage::balances();
+        let holders = self
+            .get()
+            .holders
+            .iter()
+            .map(|holder| (*holder, funcs::balance_of(balances, *holder)));
+        paginate(holders, offset, limit)
+    }
+
+    pub fn holders_count(&self) -> u32 {
+        self.get().holders.len() as u32
+    }
+
+    /// Returns a page of the exported state (admin only)
+    pub fn export_state(&self, offset: u32, limit: u32) -> StateEnvelope {
+        self.ensure_is_admin();
+        snapshot::export_page(self.get(), crate::Storage::get_session_map(), offset, limit)
+    }
+
+    /// Applies a chunk exported by a previous deployment, until the program is
+    /// sealed or the import window ends (admin only)
+    pub fn import_state(&mut self, chunk: StateEnvelope) -> Result<u32, MigrationError> {
+        self.ensure_is_admin();
+        let storage = self.get_mut();
+        assert!(exec::block_height() < storage.import_open_until, "State is sealed");
+
+        let entries = snapshot::import_chunk(storage, crate::Storage::get_session_map_mut(), chunk)?;
+        self.emit_event(Event::StateImported { entries })
+            .expect("Notification Error");
+        Ok(entries)
+    }
+
+    /// Closes the import window for good (admin only)
+    pub fn seal_state(&mut self) {
+        self.ensure_is_admin();
+        let storage = self.get_mut();
+        storage.import_open_until = storage.import_open_until.min(exec::block_height());
+        self.emit_event(Event::StateSealed)
+            .expect("Notification Error");
+    }
+
+    /// Returns the layout version of the program state
+    pub fn state_version(&self) -> u32 {
+        self.version()
     }
 }
 
@@ -300,6 +438,14 @@ impl ExtendedService {
 }
 
 
+// Skips `offset` items and takes at most `limit` (capped by MAX_PAGE_SIZE)
+fn paginate<T>(items: impl Iterator<Item = T>, offset: u32, limit: u32) -> Vec<T> {
+    items
+        .skip(offset as usize)
+        .take(limit.min(MAX_PAGE_SIZE) as usize)
+        .collect()
+}
+
 impl AsRef<VftService> for ExtendedService {
     fn as_ref(&self) -> &VftService {
         &self.vft
@@ -0,0 +1,133 @@
+// Snapshot of the token: balances from the ordered holder index, roles and
+// sessions. The total supply is derived from the imported balances.
+
+use sails_rs::{
+    collections::{BTreeMap, HashMap},
+    prelude::*,
+};
+use vft_service::Storage;
+
+use super::migration::{migrate, MigrationError, StateEnvelope, STATE_VERSION};
+use super::service::ExtendedStorage;
+use crate::{SessionData, SessionMap};
+
+pub const MAX_EXPORT_PAGE_SIZE: u32 = 200;
+
+#[derive(Debug, Clone, Copy, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum Role {
+    Admin,
+    Minter,
+    Burner,
+}
+
+// One item of the exported state. Allowances are not carried over, holders
+// approve spenders again on the new program.
+#[derive(Debug, Clone, Encode, Decode, TypeInfo)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum StateEntry {
+    Balance(ActorId, U256),
+    Role(Role, ActorId),
+    Session(ActorId, SessionData),
+}
+
+// Every entry of the state in a deterministic order, so pages can be fetched one by one
+pub fn state_entries<'a>(
+    storage: &'a ExtendedStorage,
+    balances: &'a HashMap<ActorId, U256>,
+    sessions: &'a SessionMap,
+) -> impl Iterator<Item = StateEntry> + 'a {
+    let balance_entries = storage
+        .holders
+        .iter()
+        .map(move |holder| StateEntry::Balance(*holder, balances[holder]));
+    let roles = storage
+        .admins
+        .iter()
+        .map(|admin| StateEntry::Role(Role::Admin, *admin))
+        .chain(storage.minters.iter().map(|minter| StateEntry::Role(Role::Minter, *minter)))
+        .chain(storage.burners.iter().map(|burner| StateEntry::Role(Role::Burner, *burner)));
+    let session_entries = sessions
+        .iter()
+        .map(|((account, _), session)| StateEntry::Session(*account, session.clone()));
+
+    balance_entries.chain(roles).chain(session_entries)
+}
+
+pub fn export_page(
+    storage: &ExtendedStorage,
+    sessions: &SessionMap,
+    offset: u32,
+    limit: u32,
+) -> StateEnvelope {
+    let entries: Vec<StateEntry> = state_entries(storage, Storage::balances(), sessions)
+        .skip(offset as usize)
+        .take(limit.min(MAX_EXPORT_PAGE_SIZE) as usize)
+        .collect();
+    StateEnvelope {
+        version: STATE_VERSION,
+        payload: entries.encode(),
+    }
+}
+
+// Brings the chunk to the current layout and applies it, returning the number of entries.
+// Imported balances replace existing ones and keep the total supply in line; the
+// whole chunk is checked before anything is written, and sending it again leaves
+// the same state.
+pub fn import_chunk(
+    storage: &mut ExtendedStorage,
+    sessions: &mut SessionMap,
+    chunk: StateEnvelope,
+) -> Result<u32, MigrationError> {
+    let chunk = migrate(chunk)?;
+    let entries = Vec::<StateEntry>::decode(&mut chunk.payload.as_slice())
+        .map_err(|_| MigrationError::InvalidPayload(chunk.version))?;
+
+    let staged: BTreeMap<ActorId, U256> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            StateEntry::Balance(holder, balance) => Some((*holder, *balance)),
+            _ => None,
+        })
+        .collect();
+    // Replaced balances are part of the supply, so removing them can't underflow
+    let replaced = staged
+        .keys()
+        .filter_map(|holder| Storage::balances().get(holder))
+        .fold(U256::zero(), |sum, balance| sum + balance);
+    let total_supply = staged
+        .values()
+        .try_fold(*Storage::total_supply() - replaced, |supply, balance| supply.checked_add(*balance))
+        .ok_or(MigrationError::InvalidPayload(chunk.version))?;
+    *Storage::total_supply() = total_supply;
+
+    let count = entries.len() as u32;
+    for entry in entries {
+        match entry {
+            StateEntry::Balance(holder, balance) => {
+                Storage::balances().insert(holder, balance);
+                if balance.is_zero() {
+                    Storage::balances().remove(&holder);
+                    storage.holders.remove(&holder);
+                } else {
+                    storage.holders.insert(holder);
+                }
+            }
+            StateEntry::Role(Role::Admin, actor) => {
+                storage.admins.insert(actor);
+            }
+            StateEntry::Role(Role::Minter, actor) => {
+                storage.minters.insert(actor);
+            }
+            StateEntry::Role(Role::Burner, actor) => {
+                storage.burners.insert(actor);
+            }
+            StateEntry::Session(account, session) => {
+                sessions.insert((account, session.key), session);
+            }
+        }
+    }
+    Ok(count)
+}
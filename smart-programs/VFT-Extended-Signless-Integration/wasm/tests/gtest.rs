@@ -0,0 +1,612 @@
+use app::{
+    services::{
+        migration::{MigrationError, StateEnvelope},
+        service::ActionsForSession,
+    },
+    Config, DeletionReason, SessionActivity, SessionData, SessionScope, SignatureData, MAX_SESSION_LIFETIME_MS,
+};
+use gtest::{BlockRunResult, Program, System};
+use gtest_helpers::{call, deploy, reply_to, INITIAL_BALANCE};
+use schnorrkel::{ExpansionMode, Keypair, MiniSecretKey};
+use sails_rs::{prelude::*, scale_codec::Compact};
+
+const ADMIN: u64 = 10;
+const ALICE: u64 = 11;
+const KEY: u64 = 12;
+
+fn init_program(system: &System) -> Program<'_> {
+    let config = Config {
+        gas_to_delete_session: 10_000_000_000,
+        minimum_session_duration_ms: 180_000,
+        ms_per_block: 3_000,
+    };
+    deploy(
+        system,
+        wasm::WASM_BINARY,
+        ADMIN,
+        &[ADMIN, ALICE, KEY],
+        (String::from("Token"), String::from("TKN"), 18_u8, config),
+    )
+}
+
+// ADMIN opens a session for KEY through the Session service
+fn create_session(system: &System, program: &Program, allowed_actions: Vec<ActionsForSession>) {
+    create_session_with_renewal(system, program, allowed_actions, false);
+}
+
+fn create_session_with_renewal(
+    system: &System,
+    program: &Program,
+    allowed_actions: Vec<ActionsForSession>,
+    allow_renewal: bool,
+) {
+    open_session(
+        system,
+        program,
+        SignatureData {
+            allow_renewal,
+            ..signature_data(allowed_actions)
+        },
+    );
+}
+
+// Session of ADMIN held by KEY, without limits
+fn signature_data(allowed_actions: Vec<ActionsForSession>) -> SignatureData {
+    SignatureData {
+        key: ActorId::from(KEY),
+        duration: 180_000,
+        allowed_actions,
+        spend_limits: vec![],
+        max_calls: None,
+        scope: SessionScope::default(),
+        allow_renewal: false,
+    }
+}
+
+// ADMIN opens a session described by `signature_data`
+fn open_session(system: &System, program: &Program, signature_data: SignatureData) -> BlockRunResult {
+    let id = program.send_bytes(
+        ADMIN,
+        call("Session", "CreateSession", (signature_data, None::<Vec<u8>>)),
+    );
+    let result = system.run_next_block();
+    assert!(result.succeed.contains(&id));
+    result
+}
+
+// Whether the block emitted `event`, encoded as the service route, event name and fields
+fn emitted(result: &BlockRunResult, event: Vec<u8>) -> bool {
+    result
+        .log()
+        .iter()
+        .any(|log| log.destination() == ActorId::zero() && log.payload() == event.as_slice())
+}
+
+fn mint_through_session(program: &Program, amount: u64) -> MessageId {
+    program.send_bytes(
+        KEY,
+        call(
+            "Service",
+            "Mint",
+            (ActorId::from(ALICE), U256::from(amount), Some(ActorId::from(ADMIN))),
+        ),
+    )
+}
+
+fn balance_of(system: &System, program: &Program, account: u64) -> U256 {
+    program.send_bytes(ADMIN, call("Service", "BalanceOf", ActorId::from(account)));
+    let result = system.run_next_block();
+    reply_to(&result, ADMIN).expect("No reply to balance query")
+}
+
+#[test]
+fn session_created_through_session_service_allows_mint() {
+    let system = System::new();
+    let program = init_program(&system);
+    create_session(&system, &program, vec![ActionsForSession::Mint]);
+
+    mint_through_session(&program, 100);
+    let result = system.run_next_block();
+    assert_eq!(reply_to::<bool>(&result, KEY), Some(true));
+    assert_eq!(balance_of(&system, &program, ALICE), U256::from(100));
+}
+
+#[test]
+fn mint_without_session_is_rejected() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    let id = mint_through_session(&program, 100);
+    assert!(system.run_next_block().failed.contains(&id));
+    assert_eq!(balance_of(&system, &program, ALICE), U256::zero());
+}
+
+#[test]
+fn session_without_mint_action_is_rejected() {
+    let system = System::new();
+    let program = init_program(&system);
+    create_session(&system, &program, vec![ActionsForSession::Burn]);
+
+    let id = mint_through_session(&program, 100);
+    assert!(system.run_next_block().failed.contains(&id));
+    assert_eq!(balance_of(&system, &program, ALICE), U256::zero());
+}
+
+#[test]
+fn revoked_session_no_longer_allows_mint() {
+    let system = System::new();
+    let program = init_program(&system);
+    create_session(&system, &program, vec![ActionsForSession::Mint]);
+
+    let id = program.send_bytes(
+        ADMIN,
+        call("Session", "DeleteSessionKey", ActorId::from(KEY)),
+    );
+    assert!(system.run_next_block().succeed.contains(&id));
+
+    let id = mint_through_session(&program, 100);
+    assert!(system.run_next_block().failed.contains(&id));
+}
+
+#[test]
+fn account_level_routes_cover_every_session_key() {
+    let system = System::new();
+    let program = init_program(&system);
+    create_session(&system, &program, vec![ActionsForSession::Mint]);
+    // A later block gives the second session a later creation time
+    system.run_next_block();
+    let id = program.send_bytes(
+        ADMIN,
+        call(
+            "Session",
+            "CreateSession",
+            (
+                SignatureData {
+                    key: ActorId::from(ALICE),
+                    duration: 180_000,
+                    allowed_actions: vec![ActionsForSession::Burn],
+                    spend_limits: vec![],
+                    max_calls: None,
+                    scope: SessionScope::default(),
+                    allow_renewal: false,
+                },
+                None::<Vec<u8>>,
+            ),
+        ),
+    );
+    assert!(system.run_next_block().succeed.contains(&id));
+
+    // The account route returns the newest session
+    program.send_bytes(ADMIN, call("Session", "SessionForTheAccount", ActorId::from(ADMIN)));
+    let result = system.run_next_block();
+    let newest = reply_to::<Option<SessionData>>(&result, ADMIN).flatten().expect("No session");
+    assert_eq!(newest.key, ActorId::from(ALICE));
+
+    let id = program.send_bytes(ADMIN, call("Session", "DeleteSessionFromAccount", ()));
+    assert!(system.run_next_block().succeed.contains(&id));
+    program.send_bytes(ADMIN, call("Session", "SessionForTheKey", (ActorId::from(ADMIN), ActorId::from(KEY))));
+    let result = system.run_next_block();
+    assert_eq!(reply_to::<Option<SessionData>>(&result, ADMIN), Some(None));
+    let id = mint_through_session(&program, 100);
+    assert!(system.run_next_block().failed.contains(&id));
+}
+
+#[test]
+fn holders_follow_mints_transfers_and_burns() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    let id = program.send_bytes(ADMIN, call("Service", "Mint", (ActorId::from(ALICE), U256::from(100), None::<ActorId>)));
+    assert!(system.run_next_block().succeed.contains(&id));
+    let id = program.send_bytes(ALICE, call("Service", "Transfer", (ActorId::from(ADMIN), U256::from(100))));
+    assert!(system.run_next_block().succeed.contains(&id));
+
+    program.send_bytes(ADMIN, call("Service", "Holders", (0_u32, 10_u32)));
+    let result = system.run_next_block();
+    assert_eq!(
+        reply_to::<Vec<(ActorId, U256)>>(&result, ADMIN),
+        Some(vec![(ActorId::from(ADMIN), U256::from(100))])
+    );
+
+    let id = program.send_bytes(ADMIN, call("Service", "Burn", (ActorId::from(ADMIN), U256::from(100), None::<ActorId>)));
+    assert!(system.run_next_block().succeed.contains(&id));
+    program.send_bytes(ADMIN, call("Service", "HoldersCount", ()));
+    let result = system.run_next_block();
+    assert_eq!(reply_to::<u32>(&result, ADMIN), Some(0));
+}
+
+#[test]
+fn session_approves_on_behalf_of_the_account() {
+    let system = System::new();
+    let program = init_program(&system);
+    create_session(&system, &program, vec![ActionsForSession::Approve]);
+
+    program.send_bytes(
+        KEY,
+        call("Service", "Approve", (ActorId::from(ALICE), U256::from(40), Some(ActorId::from(ADMIN)))),
+    );
+    let result = system.run_next_block();
+    assert_eq!(reply_to::<()>(&result, KEY), Some(()));
+    let approval = (ActorId::from(ADMIN), ActorId::from(ALICE), U256::from(40));
+    assert!(emitted(&result, call("Service", "Approval", approval)));
+
+    program.send_bytes(ADMIN, call("Service", "Allowance", (ActorId::from(ADMIN), ActorId::from(ALICE))));
+    let result = system.run_next_block();
+    assert_eq!(reply_to::<U256>(&result, ADMIN), Some(U256::from(40)));
+
+    program.send_bytes(ADMIN, call("Service", "Allowance", (ActorId::from(KEY), ActorId::from(ALICE))));
+    let result = system.run_next_block();
+    assert_eq!(reply_to::<U256>(&result, ADMIN), Some(U256::zero()));
+}
+
+fn extend_session(program: &Program, from: u64, new_duration: u64) -> MessageId {
+    program.send_bytes(
+        from,
+        call("Session", "ExtendSession", (ActorId::from(ADMIN), ActorId::from(KEY), new_duration)),
+    )
+}
+
+fn session_for_the_key(system: &System, program: &Program) -> Option<SessionData> {
+    program.send_bytes(
+        ADMIN,
+        call("Session", "SessionForTheKey", (ActorId::from(ADMIN), ActorId::from(KEY))),
+    );
+    let result = system.run_next_block();
+    reply_to(&result, ADMIN).expect("No reply to session query")
+}
+
+#[test]
+fn renewable_session_can_be_extended_by_its_key() {
+    let system = System::new();
+    let program = init_program(&system);
+    create_session_with_renewal(&system, &program, vec![ActionsForSession::Mint], true);
+
+    let id = extend_session(&program, KEY, 600_000);
+    assert!(system.run_next_block().succeed.contains(&id));
+    let extended_at = system.block_timestamp();
+    let session = session_for_the_key(&system, &program).expect("Session is missing");
+    assert_eq!(session.expires, extended_at + 600_000);
+
+    // Past the original 180 s duration the extended session still mints
+    system.run_to_block(system.block_height() + 100);
+    mint_through_session(&program, 100);
+    let result = system.run_next_block();
+    assert_eq!(reply_to::<bool>(&result, KEY), Some(true));
+}
+
+#[test]
+fn session_lifetime_is_capped_at_thirty_days() {
+    let system = System::new();
+    let program = init_program(&system);
+    create_session(&system, &program, vec![ActionsForSession::Mint]);
+
+    let id = extend_session(&program, ADMIN, MAX_SESSION_LIFETIME_MS);
+    assert!(system.run_next_block().failed.contains(&id));
+    let id = extend_session(&program, ADMIN, u64::MAX);
+    assert!(system.run_next_block().failed.contains(&id));
+
+    let id = extend_session(&program, ADMIN, MAX_SESSION_LIFETIME_MS / 2);
+    assert!(system.run_next_block().succeed.contains(&id));
+}
+
+#[test]
+fn key_cannot_extend_a_session_without_renewal() {
+    let system = System::new();
+    let program = init_program(&system);
+    create_session(&system, &program, vec![ActionsForSession::Mint]);
+    let expires = session_for_the_key(&system, &program).expect("Session is missing").expires;
+
+    let id = extend_session(&program, KEY, 600_000);
+    assert!(system.run_next_block().failed.contains(&id));
+    assert_eq!(session_for_the_key(&system, &program).map(|session| session.expires), Some(expires));
+
+    let id = extend_session(&program, ADMIN, 600_000);
+    assert!(system.run_next_block().succeed.contains(&id));
+}
+
+fn session_activity(system: &System, program: &Program) -> Vec<SessionActivity> {
+    program.send_bytes(ADMIN, call("Session", "SessionActivity", (ActorId::from(ADMIN), 0_u32, 10_u32)));
+    let result = system.run_next_block();
+    reply_to(&result, ADMIN).expect("No reply to activity query")
+}
+
+#[test]
+fn session_activity_outlives_the_session() {
+    let system = System::new();
+    let program = init_program(&system);
+    create_session(&system, &program, vec![ActionsForSession::Mint]);
+    open_session(
+        &system,
+        &program,
+        SignatureData {
+            key: ActorId::from(ALICE),
+            ..signature_data(vec![ActionsForSession::Mint])
+        },
+    );
+
+    mint_through_session(&program, 100);
+    system.run_next_block();
+    program.send_bytes(
+        ALICE,
+        call("Service", "Mint", (ActorId::from(ALICE), U256::from(50), Some(ActorId::from(ADMIN)))),
+    );
+    system.run_next_block();
+
+    // The logs of both keys are merged, newest first
+    let keys: Vec<ActorId> = session_activity(&system, &program).iter().map(|entry| entry.key).collect();
+    assert_eq!(keys, vec![ActorId::from(ALICE), ActorId::from(KEY)]);
+
+    let id = program.send_bytes(ADMIN, call("Session", "DeleteSessionKey", ActorId::from(KEY)));
+    assert!(system.run_next_block().succeed.contains(&id));
+    assert_eq!(session_activity(&system, &program).len(), 2);
+
+    // A new session of the key starts a new log
+    create_session(&system, &program, vec![ActionsForSession::Mint]);
+    let activity = session_activity(&system, &program);
+    assert_eq!(activity.len(), 1);
+    assert_eq!(activity[0].key, ActorId::from(ALICE));
+}
+
+// Opens a one-call session and uses it up: it is stale but not expired yet
+fn exhaust_session(system: &System, program: &Program) {
+    open_session(
+        system,
+        program,
+        SignatureData {
+            max_calls: Some(1),
+            ..signature_data(vec![ActionsForSession::Mint])
+        },
+    );
+    mint_through_session(program, 100);
+    system.run_next_block();
+    let session = session_for_the_key(system, program).expect("Session is missing");
+    assert_eq!(session.max_calls, Some(0));
+}
+
+#[test]
+fn clean_up_removes_exhausted_sessions() {
+    let system = System::new();
+    let program = init_program(&system);
+    exhaust_session(&system, &program);
+
+    program.send_bytes(ALICE, call("Session", "CleanUpSessions", ()));
+    let result = system.run_next_block();
+    assert_eq!(reply_to::<u32>(&result, ALICE), Some(1));
+    let deleted = (ActorId::from(ADMIN), ActorId::from(KEY), DeletionReason::Expired);
+    assert!(emitted(&result, call("Session", "SessionDeleted", deleted)));
+    assert_eq!(session_for_the_key(&system, &program), None);
+
+    program.send_bytes(ALICE, call("Session", "CleanUpSessions", ()));
+    let result = system.run_next_block();
+    assert_eq!(reply_to::<u32>(&result, ALICE), Some(0));
+}
+
+#[test]
+fn creating_a_session_cleans_up_stale_ones() {
+    let system = System::new();
+    let program = init_program(&system);
+    exhaust_session(&system, &program);
+
+    let result = open_session(
+        &system,
+        &program,
+        SignatureData {
+            key: ActorId::from(ALICE),
+            ..signature_data(vec![ActionsForSession::Mint])
+        },
+    );
+    let deleted = (ActorId::from(ADMIN), ActorId::from(KEY), DeletionReason::Expired);
+    assert!(emitted(&result, call("Session", "SessionDeleted", deleted)));
+    assert_eq!(session_for_the_key(&system, &program), None);
+}
+
+#[test]
+fn replacing_a_stale_session_reports_its_deletion() {
+    let system = System::new();
+    let program = init_program(&system);
+    open_session(
+        &system,
+        &program,
+        SignatureData {
+            max_calls: Some(1),
+            ..signature_data(vec![ActionsForSession::Mint])
+        },
+    );
+
+    // Sessions of other accounts move the cleanup cursor past this one
+    for account in 200..210 {
+        system.mint_to(account, INITIAL_BALANCE);
+        let signature_data = SignatureData {
+            key: ActorId::from(ALICE),
+            ..signature_data(vec![ActionsForSession::Mint])
+        };
+        let id = program.send_bytes(account, call("Session", "CreateSession", (signature_data, None::<Vec<u8>>)));
+        assert!(system.run_next_block().succeed.contains(&id));
+    }
+    mint_through_session(&program, 100);
+    system.run_next_block();
+    assert_eq!(session_for_the_key(&system, &program).map(|session| session.max_calls), Some(Some(0)));
+
+    let result = open_session(&system, &program, signature_data(vec![ActionsForSession::Mint]));
+    let (account, key) = (ActorId::from(ADMIN), ActorId::from(KEY));
+    assert!(emitted(&result, call("Session", "SessionDeleted", (account, key, DeletionReason::Expired))));
+    let created = (account, key, system.block_timestamp() + 180_000, vec![ActionsForSession::Mint]);
+    assert!(emitted(&result, call("Session", "SessionCreated", created)));
+    assert_eq!(session_for_the_key(&system, &program).map(|session| session.max_calls), Some(None));
+}
+
+fn keypair(seed: u8) -> Keypair {
+    MiniSecretKey::from_bytes(&[seed; 32])
+        .expect("Invalid seed")
+        .expand_to_keypair(ExpansionMode::Ed25519)
+}
+
+// KEY opens a session for the account of `account_keypair` with a signature made by `signer`
+fn create_signed_session(program: &Program, account_keypair: &Keypair, signer: &Keypair) -> MessageId {
+    let account = ActorId::from(account_keypair.public.to_bytes());
+    let signature_data = SignatureData {
+        key: ActorId::from(KEY),
+        duration: 180_000,
+        allowed_actions: vec![ActionsForSession::Mint],
+        spend_limits: vec![],
+        max_calls: None,
+        scope: SessionScope::default(),
+        allow_renewal: false,
+    };
+    let message = [b"<Bytes>".as_slice(), &signature_data.encode(), b"</Bytes>"].concat();
+    let signature = signer.sign_simple(b"substrate", &message).to_bytes().to_vec();
+
+    program.send_bytes(
+        KEY,
+        call(
+            "Session",
+            "CreateSession",
+            (SignatureData { key: account, ..signature_data }, Some(signature)),
+        ),
+    )
+}
+
+#[test]
+fn session_signed_by_the_account_allows_mint() {
+    let system = System::new();
+    let program = init_program(&system);
+    let account_keypair = keypair(1);
+    let account = ActorId::from(account_keypair.public.to_bytes());
+
+    let id = program.send_bytes(ADMIN, call("Service", "GrantMinterRole", (account, None::<ActorId>)));
+    assert!(system.run_next_block().succeed.contains(&id));
+    let id = create_signed_session(&program, &account_keypair, &account_keypair);
+    assert!(system.run_next_block().succeed.contains(&id));
+
+    let id = program.send_bytes(
+        KEY,
+        call("Service", "Mint", (ActorId::from(ALICE), U256::from(100), Some(account))),
+    );
+    assert!(system.run_next_block().succeed.contains(&id));
+    assert_eq!(balance_of(&system, &program, ALICE), U256::from(100));
+}
+
+#[test]
+fn session_signed_by_another_key_is_rejected() {
+    let system = System::new();
+    let program = init_program(&system);
+
+    let id = create_signed_session(&program, &keypair(1), &keypair(2));
+    assert!(system.run_next_block().failed.contains(&id));
+}
+
+#[test]
+fn session_burns_only_the_account_tokens() {
+    let system = System::new();
+    let program = init_program(&system);
+    for to in [ADMIN, ALICE] {
+        let id = program.send_bytes(ADMIN, call("Service", "Mint", (ActorId::from(to), U256::from(100), None::<ActorId>)));
+        assert!(system.run_next_block().succeed.contains(&id));
+    }
+    create_session(&system, &program, vec![ActionsForSession::Burn]);
+
+    let burn = |from: u64| {
+        program.send_bytes(
+            KEY,
+            call("Service", "Burn", (ActorId::from(from), U256::from(40), Some(ActorId::from(ADMIN)))),
+        )
+    };
+    let id = burn(ALICE);
+    assert!(system.run_next_block().failed.contains(&id));
+    let id = burn(ADMIN);
+    assert!(system.run_next_block().succeed.contains(&id));
+    assert_eq!(balance_of(&system, &program, ALICE), U256::from(100));
+    assert_eq!(balance_of(&system, &program, ADMIN), U256::from(60));
+}
+
+#[test]
+fn session_grants_roles_only_to_allowed_grantees() {
+    let system = System::new();
+    let program = init_program(&system);
+    let signature_data = SignatureData {
+        key: ActorId::from(KEY),
+        duration: 180_000,
+        allowed_actions: vec![ActionsForSession::GrantMinter],
+        spend_limits: vec![],
+        max_calls: None,
+        scope: SessionScope {
+            allowed_grantees: vec![ActorId::from(ALICE)],
+            ..SessionScope::default()
+        },
+        allow_renewal: false,
+    };
+    let id = program.send_bytes(ADMIN, call("Session", "CreateSession", (signature_data, None::<Vec<u8>>)));
+    assert!(system.run_next_block().succeed.contains(&id));
+
+    let grant = |to: u64| {
+        program.send_bytes(
+            KEY,
+            call("Service", "GrantMinterRole", (ActorId::from(to), Some(ActorId::from(ADMIN)))),
+        )
+    };
+    let id = grant(KEY);
+    assert!(system.run_next_block().failed.contains(&id));
+    let id = grant(ALICE);
+    assert!(system.run_next_block().succeed.contains(&id));
+}
+
+fn balances_chunk(balances: &[(u64, U256)]) -> StateEnvelope {
+    let entries: Vec<Vec<u8>> = balances
+        .iter()
+        .map(|(holder, balance)| [vec![0], (ActorId::from(*holder), *balance).encode()].concat())
+        .collect();
+    StateEnvelope {
+        version: 1,
+        payload: [Compact(balances.len() as u32).encode(), entries.concat()].concat(),
+    }
+}
+
+fn import_state(system: &System, program: &Program, chunk: StateEnvelope) -> Option<Result<u32, MigrationError>> {
+    program.send_bytes(ADMIN, call("Service", "ImportState", chunk));
+    let result = system.run_next_block();
+    reply_to(&result, ADMIN)
+}
+
+fn total_supply(system: &System, program: &Program) -> U256 {
+    program.send_bytes(ADMIN, call("Service", "TotalSupply", ()));
+    let result = system.run_next_block();
+    reply_to(&result, ADMIN).expect("No reply to total supply query")
+}
+
+#[test]
+fn reimported_chunk_leaves_the_same_state() {
+    let system = System::new();
+    let program = init_program(&system);
+    let chunk = balances_chunk(&[(ALICE, U256::from(50)), (KEY, U256::from(30))]);
+
+    for _ in 0..2 {
+        assert_eq!(import_state(&system, &program, chunk.clone()), Some(Ok(2)));
+        assert_eq!(total_supply(&system, &program), U256::from(80));
+    }
+    assert_eq!(balance_of(&system, &program, ALICE), U256::from(50));
+}
+
+#[test]
+fn overflowing_chunk_is_rejected_as_a_whole() {
+    let system = System::new();
+    let program = init_program(&system);
+    let chunk = balances_chunk(&[(ALICE, U256::from(50)), (KEY, U256::MAX)]);
+
+    assert_eq!(
+        import_state(&system, &program, chunk),
+        Some(Err(MigrationError::InvalidPayload(1)))
+    );
+    assert_eq!(balance_of(&system, &program, ALICE), U256::zero());
+    assert_eq!(total_supply(&system, &program), U256::zero());
+}
+
+#[test]
+fn sealed_program_rejects_imports() {
+    let system = System::new();
+    let program = init_program(&system);
+    let id = program.send_bytes(ADMIN, call("Service", "SealState", ()));
+    assert!(system.run_next_block().succeed.contains(&id));
+
+    let id = program.send_bytes(ADMIN, call("Service", "ImportState", balances_chunk(&[(ALICE, U256::from(50))])));
+    assert!(system.run_next_block().failed.contains(&id));
+}
@@ -0,0 +1,41 @@
+// Helpers shared by the gtest suites of this repo's programs: deploying the
+// program under test, encoding service calls and decoding their replies.
+
+use gtest::{BlockRunResult, Program, System};
+use sails_rs::prelude::*;
+
+// Id the program under test is deployed at
+pub const PROGRAM_ID: u64 = 100;
+
+// Balance minted to every test actor so it can pay for messages and values
+pub const INITIAL_BALANCE: u128 = 1_000_000_000_000_000;
+
+/// Funds `actors`, uploads `binary` and runs its `New` constructor with `args`
+/// sent by `owner`, asserting that the initialization succeeds.
+pub fn deploy<'a>(system: &'a System, binary: &[u8], owner: u64, actors: &[u64], args: impl Encode) -> Program<'a> {
+    system.init_logger();
+    for actor in actors {
+        system.mint_to(*actor, INITIAL_BALANCE);
+    }
+
+    let program = Program::from_binary_with_id(system, PROGRAM_ID, binary);
+    let init_id = program.send_bytes(owner, ["New".encode(), args.encode()].concat());
+    assert!(system.run_next_block().succeed.contains(&init_id));
+    program
+}
+
+/// Encodes a call to `method` of the `service` route.
+pub fn call(service: &str, method: &str, args: impl Encode) -> Vec<u8> {
+    [service.encode(), method.encode(), args.encode()].concat()
+}
+
+/// Decodes the reply addressed to `actor`, skipping the service and method prefix.
+pub fn reply_to<T: Decode>(result: &BlockRunResult, actor: u64) -> Option<T> {
+    let log = result
+        .log()
+        .iter()
+        .find(|log| log.destination() == ActorId::from(actor) && log.reply_to().is_some())?;
+    let (_service, _method, value): (String, String, T) =
+        Decode::decode(&mut log.payload()).expect("Unexpected reply payload");
+    Some(value)
+}
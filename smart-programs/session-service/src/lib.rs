@@ -0,0 +1,811 @@
+#![no_std]
+
+// Session system shared by the signless programs of this repo. Each program
+// expands `generate_session_system!` with its own `ActionsForSession` enum and
+// gets the session types, the `Storage` and the `Session` service.
+
+#[doc(hidden)]
+pub use schnorrkel;
+
+// Checks an sr25519 signature made by `public_key` over `message`
+pub fn verify_signature(signature: &[u8], message: &[u8], public_key: &[u8; 32]) -> bool {
+    let Ok(signature) = schnorrkel::Signature::from_bytes(signature) else {
+        return false;
+    };
+    let Ok(public_key) = schnorrkel::PublicKey::from_bytes(public_key) else {
+        return false;
+    };
+    public_key
+        .verify_simple(b"substrate", message, &signature)
+        .is_ok()
+}
+
+// Default `on_session_removed` hook, for programs that keep no per-session state
+pub fn ignore_removed_session(_account: sails_rs::ActorId, _key: sails_rs::ActorId) {}
+
+// `on_session_removed` names a `fn(account, key)` called whenever a session is
+// removed, so the program can drop what it keeps per session
+#[macro_export]
+macro_rules! generate_session_system {
+    ($session_actions:ident) => {
+        $crate::generate_session_system!(
+            $session_actions,
+            on_session_removed = $crate::ignore_removed_session
+        );
+    };
+    ($session_actions:ident, on_session_removed = $on_session_removed:path) => {
+        use sails_rs::{
+            collections::BTreeMap,
+            gstd::{exec, msg},
+        };
+
+        // Upper bound of active session keys per account
+        pub const MAX_SESSIONS_PER_ACCOUNT: usize = 10;
+
+        // Sessions by (account, session key); an account can hold several keys,
+        // e.g. one per device, each with its own expiry and permissions
+        pub type SessionMap = BTreeMap<(ActorId, ActorId), SessionData>;
+
+        // Longest a session can live from its creation, renewals included
+        pub const MAX_SESSION_LIFETIME_MS: u64 = 30 * 24 * 60 * 60 * 1000;
+
+        // Sessions examined by each cleanup pass, so a pass costs the same
+        // however many sessions are stored
+        pub const SESSIONS_CHECKED_PER_CLEANUP: usize = 10;
+
+        // Session calls kept in the activity log of each session key, oldest dropped first
+        pub const MAX_SESSION_ACTIVITY: usize = 50;
+        pub const MAX_ACTIVITY_PAGE_SIZE: u32 = 50;
+
+        static mut SESSION_STORAGE: Option<SessionMap> = None;
+        // Activity by (account, key); it outlives the session until the key opens a new one
+        static mut SESSION_ACTIVITY: Option<BTreeMap<(ActorId, ActorId), Vec<SessionActivity>>> = None;
+        static mut SESSION_CONFIG: Option<Config> = None;
+        // Last session examined by the previous cleanup pass
+        static mut CLEANUP_CURSOR: Option<(ActorId, ActorId)> = None;
+
+        #[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+        #[codec(crate = sails_rs::scale_codec)]
+        #[scale_info(crate = sails_rs::scale_info)]
+        pub struct Config {
+            pub gas_to_delete_session: u64,
+            pub minimum_session_duration_ms: u64,
+            pub ms_per_block: u64,
+        }
+
+        // Cap on the amount of `token` a session may move, in total and per call.
+        // Native VARA uses the zero address and a program's own token its program id.
+        #[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+        #[codec(crate = sails_rs::scale_codec)]
+        #[scale_info(crate = sails_rs::scale_info)]
+        pub struct SpendLimit {
+            pub token: ActorId,
+            pub total: u128,
+            pub per_call: u128,
+        }
+
+        // What is left of a spend limit in an active session
+        #[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+        #[codec(crate = sails_rs::scale_codec)]
+        #[scale_info(crate = sails_rs::scale_info)]
+        pub struct SpendAllowance {
+            pub token: ActorId,
+            pub remaining: u128,
+            pub per_call: u128,
+        }
+
+        // Narrows what a session may do with the arguments of its calls. Empty
+        // lists and a missing `max_value` leave that argument unrestricted.
+        #[derive(Debug, Clone, Default, Encode, Decode, TypeInfo, PartialEq, Eq)]
+        #[codec(crate = sails_rs::scale_codec)]
+        #[scale_info(crate = sails_rs::scale_info)]
+        pub struct SessionScope {
+            pub allowed_recipients: Vec<ActorId>,
+            pub allowed_tokens: Vec<ActorId>,
+            pub max_value: Option<u128>,
+            // Accounts the session may grant or revoke roles for
+            pub allowed_grantees: Vec<ActorId>,
+        }
+
+        impl SessionScope {
+            pub fn check(&self, args: &CallArgs) {
+                if let Some(recipient) = args.recipient {
+                    assert!(
+                        self.allowed_recipients.is_empty()
+                            || self.allowed_recipients.contains(&recipient),
+                        "Recipient not allowed for session"
+                    );
+                }
+                if let Some(token) = args.token {
+                    assert!(
+                        self.allowed_tokens.is_empty() || self.allowed_tokens.contains(&token),
+                        "Token not allowed for session"
+                    );
+                }
+                if let (Some(max_value), Some(value)) = (self.max_value, args.value) {
+                    assert!(value <= max_value, "Value above session limit");
+                }
+                if let Some(grantee) = args.grantee {
+                    assert!(
+                        self.allowed_grantees.is_empty()
+                            || self.allowed_grantees.contains(&grantee),
+                        "Grantee not allowed for session"
+                    );
+                }
+            }
+        }
+
+        // Arguments of a session call that the session guard checks against the scope
+        #[derive(Debug, Clone, Default)]
+        pub struct CallArgs {
+            pub recipient: Option<ActorId>,
+            pub token: Option<ActorId>,
+            pub value: Option<u128>,
+            pub grantee: Option<ActorId>,
+        }
+
+        impl CallArgs {
+            pub fn with_recipient(mut self, recipient: ActorId) -> Self {
+                self.recipient = Some(recipient);
+                self
+            }
+
+            pub fn with_token(mut self, token: ActorId) -> Self {
+                self.token = Some(token);
+                self
+            }
+
+            pub fn with_value(mut self, value: u128) -> Self {
+                self.value = Some(value);
+                self
+            }
+
+            pub fn with_grantee(mut self, grantee: ActorId) -> Self {
+                self.grantee = Some(grantee);
+                self
+            }
+        }
+
+        // Data signed by the account when the session is created by its key.
+        // Without spend limits the session can move any amount, and without
+        // `max_calls` it can be used any number of times until it expires.
+        #[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+        #[codec(crate = sails_rs::scale_codec)]
+        #[scale_info(crate = sails_rs::scale_info)]
+        pub struct SignatureData {
+            pub key: ActorId,
+            pub duration: u64,
+            pub allowed_actions: Vec<$session_actions>,
+            pub spend_limits: Vec<SpendLimit>,
+            pub max_calls: Option<u32>,
+            pub scope: SessionScope,
+            // Lets the session key extend the session by itself
+            pub allow_renewal: bool,
+        }
+
+        #[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+        #[codec(crate = sails_rs::scale_codec)]
+        #[scale_info(crate = sails_rs::scale_info)]
+        pub struct SessionData {
+            pub key: ActorId,
+            pub expires: u64,
+            pub allowed_actions: Vec<$session_actions>,
+            pub expires_at_block: u32,
+            pub allowances: Vec<SpendAllowance>,
+            // Calls left, reserved by `get_actor` as each call starts
+            pub max_calls: Option<u32>,
+            pub scope: SessionScope,
+            pub allow_renewal: bool,
+            // Block timestamp (ms) the session was created at, bounds renewals
+            pub created_at: u64,
+        }
+
+        impl SessionData {
+            // A session can't be used once it expires or runs out of calls
+            pub fn is_active(&self) -> bool {
+                self.expires > exec::block_timestamp() && self.max_calls != Some(0)
+            }
+        }
+
+        // A call made through a session, as recorded by the session guard
+        #[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+        #[codec(crate = sails_rs::scale_codec)]
+        #[scale_info(crate = sails_rs::scale_info)]
+        pub struct SessionActivity {
+            pub action: $session_actions,
+            pub block: u32,
+            pub key: ActorId,
+        }
+
+        #[derive(Debug, Clone, Copy, Encode, Decode, TypeInfo, PartialEq, Eq)]
+        #[codec(crate = sails_rs::scale_codec)]
+        #[scale_info(crate = sails_rs::scale_info)]
+        pub enum DeletionReason {
+            // Removed by a cleanup pass after it expired or ran out of calls
+            Expired,
+            ByOwner,
+            // Removed by the deletion the program scheduled for itself
+            ByProgram,
+        }
+
+        #[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+        #[codec(crate = sails_rs::scale_codec)]
+        #[scale_info(crate = sails_rs::scale_info)]
+        pub enum Event {
+            SessionCreated {
+                account: ActorId,
+                key: ActorId,
+                expires: u64,
+                allowed_actions: Vec<$session_actions>,
+            },
+            SessionDeleted {
+                account: ActorId,
+                key: ActorId,
+                reason: DeletionReason,
+            },
+            SessionExtended {
+                account: ActorId,
+                key: ActorId,
+                expires: u64,
+            },
+        }
+
+        pub struct Storage;
+
+        impl Storage {
+            pub fn get_session_map() -> &'static SessionMap {
+                unsafe {
+                    SESSION_STORAGE
+                        .as_ref()
+                        .expect("Session storage is not initialized")
+                }
+            }
+
+            pub fn get_session_map_mut() -> &'static mut SessionMap {
+                unsafe {
+                    SESSION_STORAGE
+                        .as_mut()
+                        .expect("Session storage is not initialized")
+                }
+            }
+
+            pub fn get_activity() -> &'static BTreeMap<(ActorId, ActorId), Vec<SessionActivity>> {
+                unsafe {
+                    SESSION_ACTIVITY
+                        .as_ref()
+                        .expect("Session storage is not initialized")
+                }
+            }
+
+            pub fn get_activity_mut() -> &'static mut BTreeMap<(ActorId, ActorId), Vec<SessionActivity>> {
+                unsafe {
+                    SESSION_ACTIVITY
+                        .as_mut()
+                        .expect("Session storage is not initialized")
+                }
+            }
+
+            pub fn record_activity(account: ActorId, key: ActorId, action: $session_actions) {
+                let activity = Self::get_activity_mut().entry((account, key)).or_default();
+                if activity.len() >= MAX_SESSION_ACTIVITY {
+                    activity.remove(0);
+                }
+                activity.push(SessionActivity {
+                    action,
+                    block: exec::block_height(),
+                    key,
+                });
+            }
+
+            pub fn get_config() -> &'static Config {
+                unsafe {
+                    SESSION_CONFIG
+                        .as_ref()
+                        .expect("Session config is not initialized")
+                }
+            }
+
+            // Every session removal goes through here, so the program hook sees all of them
+            pub fn remove_session(account: ActorId, key: ActorId) -> Option<SessionData> {
+                let session = Self::get_session_map_mut().remove(&(account, key))?;
+                $on_session_removed(account, key);
+                Some(session)
+            }
+
+            // Sessions of `account`, ordered by key
+            pub fn sessions_of(
+                account: ActorId,
+            ) -> impl Iterator<Item = (&'static ActorId, &'static SessionData)> {
+                Self::get_session_map()
+                    .range((account, ActorId::zero())..=(account, ActorId::from([u8::MAX; 32])))
+                    .map(|((_, key), session)| (key, session))
+            }
+
+            // Examines the next `limit` sessions after the cleanup cursor and removes
+            // the ones that expired or ran out of calls. The cursor wraps around
+            // once the end of the map is reached.
+            pub fn prune_inactive(limit: usize) -> Vec<(ActorId, ActorId)> {
+                let sessions = Self::get_session_map();
+                let cursor = unsafe { CLEANUP_CURSOR };
+                let examined: Vec<(&(ActorId, ActorId), &SessionData)> = match cursor {
+                    Some(cursor) => sessions
+                        .range((core::ops::Bound::Excluded(cursor), core::ops::Bound::Unbounded))
+                        .take(limit)
+                        .collect(),
+                    None => sessions.iter().take(limit).collect(),
+                };
+                unsafe {
+                    CLEANUP_CURSOR = if examined.len() < limit {
+                        None
+                    } else {
+                        examined.last().map(|(id, _)| **id)
+                    };
+                }
+                // Collected before any removal, which needs the map mutably
+                let inactive: Vec<(ActorId, ActorId)> = examined
+                    .into_iter()
+                    .filter(|(_, session)| !session.is_active())
+                    .map(|(id, _)| *id)
+                    .collect();
+
+                for (account, key) in &inactive {
+                    Self::remove_session(*account, *key);
+                }
+                inactive
+            }
+
+            // Charges `amount` of `token` against the session of `account` held by `key`.
+            // Sessions created without spend limits are not charged.
+            pub fn spend(account: &ActorId, key: &ActorId, token: ActorId, amount: u128) {
+                let session = Self::get_session_map_mut()
+                    .get_mut(&(*account, *key))
+                    .expect("No valid session for this account");
+                if session.allowances.is_empty() {
+                    return;
+                }
+
+                let allowance = session
+                    .allowances
+                    .iter_mut()
+                    .find(|allowance| allowance.token == token)
+                    .expect("Token not allowed for session");
+                assert!(amount <= allowance.per_call, "Session per-call limit exceeded");
+                allowance.remaining = allowance
+                    .remaining
+                    .checked_sub(amount)
+                    .expect("Session spend limit exceeded");
+            }
+
+            // Gives back a call reserved by `get_actor`, if the session still exists
+            pub fn refund_call(account: &ActorId, key: &ActorId) {
+                if let Some(session) = Self::get_session_map_mut().get_mut(&(*account, *key)) {
+                    if let Some(calls) = session.max_calls.as_mut() {
+                        *calls = calls.saturating_add(1);
+                    }
+                }
+            }
+
+            // Gives back an amount charged by `spend` when the operation failed
+            pub fn refund(account: &ActorId, key: &ActorId, token: ActorId, amount: u128) {
+                let Some(session) = Self::get_session_map_mut().get_mut(&(*account, *key)) else {
+                    return;
+                };
+                if let Some(allowance) = session
+                    .allowances
+                    .iter_mut()
+                    .find(|allowance| allowance.token == token)
+                {
+                    allowance.remaining = allowance.remaining.saturating_add(amount);
+                }
+            }
+        }
+
+        // Session guard shared by every service of the program: returns the account
+        // the call acts for. With `session_for_account` the sender must hold an
+        // active session of that account that allows `action`, and the call
+        // arguments must fit the session scope. One call of the session is
+        // reserved here, so calls waiting on a reply can't overrun `max_calls`;
+        // a call that fails without panicking gives it back with `refund_session_call`.
+        pub fn get_actor(
+            msg_source: &ActorId,
+            session_for_account: &Option<ActorId>,
+            action: $session_actions,
+            args: CallArgs,
+        ) -> ActorId {
+            match session_for_account {
+                Some(account) => {
+                    let session = Storage::get_session_map_mut()
+                        .get_mut(&(*account, *msg_source))
+                        .expect("No valid session for this account");
+                    assert!(
+                        session.expires > exec::block_timestamp(),
+                        "Session expired"
+                    );
+                    assert_ne!(session.max_calls, Some(0), "Session has no calls left");
+                    assert!(
+                        session.allowed_actions.contains(&action),
+                        "Action not allowed"
+                    );
+                    session.scope.check(&args);
+                    if let Some(calls) = session.max_calls.as_mut() {
+                        *calls -= 1;
+                    }
+                    *account
+                }
+                None => *msg_source,
+            }
+        }
+
+        // Adds a session call to the account's activity log. Called on the
+        // success path of a session call, so failed calls are not logged.
+        pub fn record_session_call(
+            msg_source: &ActorId,
+            session_for_account: &Option<ActorId>,
+            action: $session_actions,
+        ) {
+            let Some(account) = session_for_account else {
+                return;
+            };
+            // The session may have been removed while an async call was waiting
+            if !Storage::get_session_map().contains_key(&(*account, *msg_source)) {
+                return;
+            }
+            Storage::record_activity(*account, *msg_source, action);
+        }
+
+        // Gives back the call reserved by `get_actor` when the call failed
+        pub fn refund_session_call(msg_source: &ActorId, session_for_account: &Option<ActorId>) {
+            if let Some(account) = session_for_account {
+                Storage::refund_call(account, msg_source);
+            }
+        }
+
+        fn check_if_session_exists(account: ActorId, key: ActorId) {
+            let mut active_keys = 0;
+            for (session_key, session) in Storage::sessions_of(account) {
+                if !session.is_active() {
+                    continue;
+                }
+                assert_ne!(*session_key, key, "You already have an active session");
+                active_keys += 1;
+            }
+            assert!(
+                active_keys < MAX_SESSIONS_PER_ACCOUNT,
+                "Too many active sessions"
+            );
+        }
+
+        pub struct SessionService(());
+
+        impl SessionService {
+            pub fn init(config: Config) -> Self {
+                unsafe {
+                    SESSION_STORAGE = Some(BTreeMap::new());
+                    SESSION_ACTIVITY = Some(BTreeMap::new());
+                    SESSION_CONFIG = Some(config);
+                }
+                Self(())
+            }
+
+            // Blocks covering `duration`, rounded up
+            fn blocks_for(duration: u64) -> u32 {
+                u32::try_from(duration.div_ceil(Storage::get_config().ms_per_block))
+                    .expect("Duration is too large")
+            }
+
+            // Asks the program to delete the session once `number_of_blocks` have passed
+            fn schedule_deletion(account: ActorId, key: ActorId, number_of_blocks: u32) {
+                let request = [
+                    "Session".encode(),
+                    "DeleteSessionKeyFromProgram".encode(),
+                    account.encode(),
+                    key.encode(),
+                ]
+                .concat();
+                msg::send_bytes_with_gas_delayed(
+                    exec::program_id(),
+                    request,
+                    Storage::get_config().gas_to_delete_session,
+                    0,
+                    number_of_blocks,
+                )
+                .expect("Error in sending a delayed message");
+            }
+
+            fn delete_if_due(&mut self, account: ActorId, key: ActorId) {
+                let Some(session) = Storage::get_session_map().get(&(account, key)) else {
+                    return;
+                };
+                // An extended session outlives the deletion scheduled before the extension
+                if session.expires_at_block > exec::block_height() {
+                    return;
+                }
+                Storage::remove_session(account, key);
+                self.emit_event(Event::SessionDeleted {
+                    account,
+                    key,
+                    reason: DeletionReason::ByProgram,
+                })
+                .expect("Notification Error");
+            }
+
+            fn clean_up(&mut self, limit: usize) -> u32 {
+                let removed = Storage::prune_inactive(limit);
+                for (account, key) in &removed {
+                    self.emit_event(Event::SessionDeleted {
+                        account: *account,
+                        key: *key,
+                        reason: DeletionReason::Expired,
+                    })
+                    .expect("Notification Error");
+                }
+                removed.len() as u32
+            }
+        }
+
+        #[sails_rs::service(events = Event)]
+        impl SessionService {
+            pub fn new() -> Self {
+                Self(())
+            }
+
+            /// Opens a session. Without a signature the sender is the account and
+            /// `signature_data.key` its session key; with a signature the sender is
+            /// the key and `signature_data.key` the account that signed it.
+            pub fn create_session(&mut self, signature_data: SignatureData, signature: Option<Vec<u8>>) {
+                let msg_source = msg::source();
+                let config = Storage::get_config();
+
+                assert!(
+                    signature_data.duration >= config.minimum_session_duration_ms,
+                    "Duration is too small"
+                );
+                assert!(
+                    !signature_data.allowed_actions.is_empty(),
+                    "No messages for approval"
+                );
+                assert_ne!(signature_data.max_calls, Some(0), "Session without calls");
+                for limit in &signature_data.spend_limits {
+                    assert!(limit.per_call <= limit.total, "Per-call limit above total limit");
+                }
+
+                assert!(
+                    signature_data.duration <= MAX_SESSION_LIFETIME_MS,
+                    "Duration is too large"
+                );
+
+                let created_at = exec::block_timestamp();
+                let expires = created_at + signature_data.duration;
+                let number_of_blocks = Self::blocks_for(signature_data.duration);
+                let expires_at_block = exec::block_height() + number_of_blocks;
+                let allowances: Vec<SpendAllowance> = signature_data
+                    .spend_limits
+                    .iter()
+                    .map(|limit| SpendAllowance {
+                        token: limit.token,
+                        remaining: limit.total,
+                        per_call: limit.per_call,
+                    })
+                    .collect();
+
+                let (account, key) = match signature {
+                    Some(signature) => {
+                        let account = signature_data.key;
+                        // The account signed the same data with the key in place of its own address
+                        let message = SignatureData {
+                            key: msg_source,
+                            ..signature_data.clone()
+                        }
+                        .encode();
+                        let message = [b"<Bytes>".as_slice(), &message, b"</Bytes>"].concat();
+                        assert!(
+                            $crate::verify_signature(&signature, &message, &account.into()),
+                            "Failed sign verification"
+                        );
+                        (account, msg_source)
+                    }
+                    None => (msg_source, signature_data.key),
+                };
+
+                // Every new session pays for a bounded cleanup of stale ones
+                self.clean_up(SESSIONS_CHECKED_PER_CLEANUP);
+                check_if_session_exists(account, key);
+                // A stale session of the same key is replaced by the new one, which
+                // starts with an empty activity log
+                if Storage::remove_session(account, key).is_some() {
+                    self.emit_event(Event::SessionDeleted {
+                        account,
+                        key,
+                        reason: DeletionReason::Expired,
+                    })
+                    .expect("Notification Error");
+                }
+                Storage::get_activity_mut().remove(&(account, key));
+                Storage::get_session_map_mut().insert(
+                    (account, key),
+                    SessionData {
+                        key,
+                        expires,
+                        allowed_actions: signature_data.allowed_actions.clone(),
+                        expires_at_block,
+                        allowances,
+                        max_calls: signature_data.max_calls,
+                        scope: signature_data.scope,
+                        allow_renewal: signature_data.allow_renewal,
+                        created_at,
+                    },
+                );
+
+                Self::schedule_deletion(account, key, number_of_blocks);
+
+                self.emit_event(Event::SessionCreated {
+                    account,
+                    key,
+                    expires,
+                    allowed_actions: signature_data.allowed_actions,
+                })
+                .expect("Notification Error");
+            }
+
+            /// Moves the expiry of an active session to `new_duration` ms from now.
+            /// The account can always do it; the session key only when the account
+            /// allowed renewal when creating the session.
+            pub fn extend_session(&mut self, account: ActorId, key: ActorId, new_duration: u64) {
+                let msg_source = msg::source();
+                let config = Storage::get_config();
+                let session = Storage::get_session_map_mut()
+                    .get_mut(&(account, key))
+                    .expect("No session");
+                assert!(
+                    msg_source == account || (msg_source == key && session.allow_renewal),
+                    "Not allowed to extend session"
+                );
+                assert!(session.is_active(), "Session is not active");
+                assert!(
+                    new_duration >= config.minimum_session_duration_ms,
+                    "Duration is too small"
+                );
+
+                let expires = exec::block_timestamp()
+                    .checked_add(new_duration)
+                    .expect("Duration is too large");
+                assert!(
+                    expires.saturating_sub(session.created_at) <= MAX_SESSION_LIFETIME_MS,
+                    "Session lifetime exceeded"
+                );
+                let number_of_blocks = Self::blocks_for(new_duration);
+                session.expires = expires;
+                session.expires_at_block = exec::block_height()
+                    .checked_add(number_of_blocks)
+                    .expect("Duration is too large");
+
+                Self::schedule_deletion(account, key, number_of_blocks);
+
+                self.emit_event(Event::SessionExtended {
+                    account,
+                    key,
+                    expires,
+                })
+                .expect("Notification Error");
+            }
+
+            /// Removes a session once its scheduled expiry block is reached; only the
+            /// program itself (delayed message) can call it
+            pub fn delete_session_key_from_program(&mut self, session_for_account: ActorId, key: ActorId) {
+                assert_eq!(
+                    exec::program_id(),
+                    msg::source(),
+                    "The msg source must correspond to the program address"
+                );
+                self.delete_if_due(session_for_account, key);
+            }
+
+            /// Removes the sessions of an account whose expiry block is reached; only
+            /// the program itself (delayed message) can call it. Kept for deletions
+            /// scheduled before sessions were keyed, see `delete_session_key_from_program`.
+            pub fn delete_session_from_program(&mut self, session_for_account: ActorId) {
+                assert_eq!(
+                    exec::program_id(),
+                    msg::source(),
+                    "The msg source must correspond to the program address"
+                );
+                let keys: Vec<ActorId> = Storage::sessions_of(session_for_account)
+                    .map(|(key, _)| *key)
+                    .collect();
+                for key in keys {
+                    self.delete_if_due(session_for_account, key);
+                }
+            }
+
+            /// Revokes one session key of the sender's account
+            pub fn delete_session_key(&mut self, key: ActorId) {
+                let account = msg::source();
+                Storage::remove_session(account, key).expect("No session");
+                self.emit_event(Event::SessionDeleted {
+                    account,
+                    key,
+                    reason: DeletionReason::ByOwner,
+                })
+                .expect("Notification Error");
+            }
+
+            /// Revokes every session key of the sender's account
+            pub fn delete_session_from_account(&mut self) {
+                let account = msg::source();
+                let keys: Vec<ActorId> = Storage::sessions_of(account)
+                    .map(|(key, _)| *key)
+                    .collect();
+                assert!(!keys.is_empty(), "No session");
+
+                for key in keys {
+                    Storage::remove_session(account, key);
+                    self.emit_event(Event::SessionDeleted {
+                        account,
+                        key,
+                        reason: DeletionReason::ByOwner,
+                    })
+                    .expect("Notification Error");
+                }
+            }
+
+            /// Removes expired or exhausted sessions among the next batch of stored
+            /// sessions; anyone can call it. Returns the number removed.
+            pub fn clean_up_sessions(&mut self) -> u32 {
+                self.clean_up(SESSIONS_CHECKED_PER_CLEANUP)
+            }
+
+            pub fn sessions(&self) -> Vec<(ActorId, SessionData)> {
+                Storage::get_session_map()
+                    .iter()
+                    .map(|((account, _), session)| (*account, session.clone()))
+                    .collect()
+            }
+
+            /// Returns the newest session of an account
+            pub fn session_for_the_account(&self, account: ActorId) -> Option<SessionData> {
+                Storage::sessions_of(account)
+                    .map(|(_, session)| session)
+                    .max_by_key(|session| session.created_at)
+                    .cloned()
+            }
+
+            /// Returns the session of an account held by `key`
+            pub fn session_for_the_key(&self, account: ActorId, key: ActorId) -> Option<SessionData> {
+                Storage::get_session_map().get(&(account, key)).cloned()
+            }
+
+            /// Returns the sessions of an account that can still be used, one per key
+            pub fn active_sessions_of(&self, account: ActorId) -> Vec<SessionData> {
+                Storage::sessions_of(account)
+                    .filter(|(_, session)| session.is_active())
+                    .map(|(_, session)| session.clone())
+                    .collect()
+            }
+
+            /// Returns the calls made through the sessions of an account, newest first
+            pub fn session_activity(&self, account: ActorId, offset: u32, limit: u32) -> Vec<SessionActivity> {
+                let mut activity: Vec<&SessionActivity> = Storage::get_activity()
+                    .range((account, ActorId::zero())..=(account, ActorId::from([u8::MAX; 32])))
+                    .flat_map(|(_, log)| log.iter().rev())
+                    .collect();
+                // Stable, so calls of one key in the same block keep their newest first order
+                activity.sort_by(|a, b| b.block.cmp(&a.block));
+                activity
+                    .into_iter()
+                    .skip(offset as usize)
+                    .take(limit.min(MAX_ACTIVITY_PAGE_SIZE) as usize)
+                    .cloned()
+                    .collect()
+            }
+
+            /// Returns what is left of each spend limit of a session; empty when
+            /// the session has no limits
+            pub fn remaining_allowance(&self, account: ActorId, key: ActorId) -> Vec<SpendAllowance> {
+                Storage::get_session_map()
+                    .get(&(account, key))
+                    .map(|session| session.allowances.clone())
+                    .unwrap_or_default()
+            }
+        }
+    };
+}
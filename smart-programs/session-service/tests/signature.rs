@@ -0,0 +1,51 @@
+use schnorrkel::{ExpansionMode, Keypair, MiniSecretKey};
+use session_service::verify_signature;
+
+// Wraps the payload the way wallets do before signing raw bytes
+fn wrapped(payload: &[u8]) -> Vec<u8> {
+    [b"<Bytes>".as_slice(), payload, b"</Bytes>"].concat()
+}
+
+fn keypair(seed: u8) -> Keypair {
+    MiniSecretKey::from_bytes(&[seed; 32])
+        .expect("Invalid seed")
+        .expand_to_keypair(ExpansionMode::Ed25519)
+}
+
+fn sign(keypair: &Keypair, message: &[u8]) -> Vec<u8> {
+    keypair.sign_simple(b"substrate", message).to_bytes().to_vec()
+}
+
+#[test]
+fn signature_by_the_account_is_accepted() {
+    let account = keypair(1);
+    let message = wrapped(b"session data");
+    let signature = sign(&account, &message);
+
+    assert!(verify_signature(&signature, &message, &account.public.to_bytes()));
+}
+
+#[test]
+fn tampered_message_is_rejected() {
+    let account = keypair(1);
+    let signature = sign(&account, &wrapped(b"session data"));
+
+    assert!(!verify_signature(&signature, &wrapped(b"session datb"), &account.public.to_bytes()));
+}
+
+#[test]
+fn signature_by_another_key_is_rejected() {
+    let account = keypair(1);
+    let message = wrapped(b"session data");
+    let signature = sign(&keypair(2), &message);
+
+    assert!(!verify_signature(&signature, &message, &account.public.to_bytes()));
+}
+
+#[test]
+fn malformed_signature_is_rejected() {
+    let account = keypair(1);
+    let message = wrapped(b"session data");
+
+    assert!(!verify_signature(&[0; 10], &message, &account.public.to_bytes()));
+}
@@ -0,0 +1,73 @@
+#![no_std]
+
+// Versioned state shared by the programs of this repo. Each program keeps its
+// own `STATE_VERSION` and list of migrations and passes them to `migrate`.
+
+use sails_rs::prelude::*;
+
+// Blocks after deployment during which snapshots can be imported, about a day
+// at 3 s per block. Past it the import window closes by itself, as if the
+// program had been sealed.
+pub const IMPORT_WINDOW_BLOCKS: u32 = 28_800;
+
+// In-memory state together with the layout version it was written with
+#[derive(Debug, Clone, Default)]
+pub struct VersionedState<T> {
+    pub version: u32,
+    pub state: T,
+}
+
+impl<T> VersionedState<T> {
+    pub fn new(version: u32, state: T) -> Self {
+        Self { version, state }
+    }
+}
+
+// Encoded state tagged with its layout version, as it leaves or enters the program
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub struct StateEnvelope {
+    pub version: u32,
+    pub payload: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Encode, Decode, TypeInfo, PartialEq, Eq)]
+#[codec(crate = sails_rs::scale_codec)]
+#[scale_info(crate = sails_rs::scale_info)]
+pub enum MigrationError {
+    // The envelope was written by a newer build
+    UnsupportedVersion(u32),
+    MissingMigration(u32),
+    InvalidPayload(u32),
+}
+
+// Converts a payload written with version `n` into the layout of version `n + 1`
+pub type Migration = fn(&[u8]) -> Result<Vec<u8>, MigrationError>;
+
+// Applies `migrations`, given as (version they upgrade from, migration), in
+// order until the envelope reaches `current`
+pub fn migrate(
+    envelope: StateEnvelope,
+    current: u32,
+    migrations: &[(u32, Migration)],
+) -> Result<StateEnvelope, MigrationError> {
+    let StateEnvelope {
+        mut version,
+        mut payload,
+    } = envelope;
+    if version > current {
+        return Err(MigrationError::UnsupportedVersion(version));
+    }
+
+    while version < current {
+        let (_, migration) = migrations
+            .iter()
+            .find(|(from, _)| *from == version)
+            .ok_or(MigrationError::MissingMigration(version))?;
+        payload = migration(&payload)?;
+        version += 1;
+    }
+
+    Ok(StateEnvelope { version, payload })
+}
@@ -0,0 +1,57 @@
+use state_migration::{migrate, Migration, MigrationError, StateEnvelope};
+
+fn envelope(version: u32, payload: &[u8]) -> StateEnvelope {
+    StateEnvelope {
+        version,
+        payload: payload.to_vec(),
+    }
+}
+
+fn append_one(payload: &[u8]) -> Result<Vec<u8>, MigrationError> {
+    Ok([payload, &[1]].concat())
+}
+
+fn append_two(payload: &[u8]) -> Result<Vec<u8>, MigrationError> {
+    Ok([payload, &[2]].concat())
+}
+
+fn reject(_payload: &[u8]) -> Result<Vec<u8>, MigrationError> {
+    Err(MigrationError::InvalidPayload(2))
+}
+
+const MIGRATIONS: &[(u32, Migration)] = &[(2, append_two), (1, append_one)];
+
+#[test]
+fn current_envelope_is_left_as_is() {
+    assert_eq!(migrate(envelope(3, &[0]), 3, MIGRATIONS), Ok(envelope(3, &[0])));
+}
+
+#[test]
+fn migrations_are_applied_in_version_order() {
+    assert_eq!(migrate(envelope(1, &[0]), 3, MIGRATIONS), Ok(envelope(3, &[0, 1, 2])));
+    assert_eq!(migrate(envelope(2, &[0]), 3, MIGRATIONS), Ok(envelope(3, &[0, 2])));
+}
+
+#[test]
+fn newer_envelope_is_rejected() {
+    assert_eq!(
+        migrate(envelope(4, &[0]), 3, MIGRATIONS),
+        Err(MigrationError::UnsupportedVersion(4))
+    );
+}
+
+#[test]
+fn gap_in_migrations_is_reported() {
+    assert_eq!(
+        migrate(envelope(0, &[0]), 3, MIGRATIONS),
+        Err(MigrationError::MissingMigration(0))
+    );
+}
+
+#[test]
+fn failed_migration_stops_the_upgrade() {
+    assert_eq!(
+        migrate(envelope(2, &[0]), 3, &[(2, reject)]),
+        Err(MigrationError::InvalidPayload(2))
+    );
+}